@@ -0,0 +1,149 @@
+use core::fmt;
+use glob::Pattern;
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{fmt_single_parameter, Args},
+    git,
+    logger::log_error,
+};
+
+/// Prefixes tried when suggesting a branch name that follows `pattern`
+const PREFIXES: [&str; 8] = ["feature/", "feat/", "fix/", "bugfix/", "hotfix/", "chore/", "docs/", "release/"];
+
+/**
+ * Naming policy for branches, and branches that may not receive direct
+ * commits or pushes, given as glob patterns
+ */
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Branch {
+    /// Regex every branch name must match
+    pub pattern: Option<String>,
+    /// Glob patterns of the branches that may not receive direct commits or pushes
+    pub protected: Option<Vec<String>>,
+}
+
+impl Branch {
+    /**
+     * Reject commits on a protected or badly named current branch
+     */
+    pub fn check_commit(&self) {
+        // A detached HEAD is not a branch
+        let branch = match git::git(&["symbolic-ref", "--short", "-q", "HEAD"]) {
+            Some(branch) => branch,
+            None => return,
+        };
+        if self.is_protected(&branch) {
+            log_error(
+                &format!("direct commits to '{}' are not allowed, commit on another branch", branch),
+                true,
+            );
+        }
+        self.check_name(&branch);
+    }
+
+    /**
+     * Reject pushes to protected or badly named remote branches, `stdin` holds
+     * `<local ref> <local sha> <remote ref> <remote sha>` lines
+     */
+    pub fn check_push(&self, stdin: &[u8]) {
+        for line in String::from_utf8_lossy(stdin).lines() {
+            let remote_ref = match line.split_whitespace().nth(2) {
+                Some(remote_ref) => remote_ref,
+                None => continue,
+            };
+            let branch = match remote_ref.strip_prefix("refs/heads/") {
+                Some(branch) => branch,
+                None => continue,
+            };
+            if self.is_protected(branch) {
+                log_error(
+                    &format!("direct pushes to '{}' are not allowed, open a merge request", branch),
+                    true,
+                );
+            }
+            self.check_name(branch);
+        }
+    }
+
+    fn is_protected(&self, branch: &str) -> bool {
+        self.protected.iter().flatten().any(|protected| match Pattern::new(protected) {
+            Ok(pattern) => pattern.matches(branch),
+            Err(_) => protected == branch,
+        })
+    }
+
+    fn check_name(&self, branch: &str) {
+        let pattern = match &self.pattern {
+            Some(pattern) => pattern,
+            None => return,
+        };
+        let re = match Regex::new(pattern) {
+            Ok(re) => re,
+            Err(e) => {
+                log_error(&format!("invalid branch pattern `{}`: {}", pattern, e), true);
+                unreachable!();
+            }
+        };
+        if re.is_match(branch) {
+            return;
+        }
+        let hint = match suggest(&re, branch) {
+            Some(name) => format!("try `git branch -m {}`", name),
+            None => format!("branch names must match `{}`", pattern),
+        };
+        log_error(&format!("branch name '{}' is not allowed, {}", branch, hint), true);
+    }
+}
+
+/**
+ * A name close to `branch` matching `re`: normalized, then with a usual prefix
+ */
+fn suggest(re: &Regex, branch: &str) -> Option<String> {
+    let mut normalized = String::new();
+    for c in branch.to_lowercase().chars() {
+        let c = if c.is_ascii_alphanumeric() || c == '/' || c == '.' { c } else { '-' };
+        if !(c == '-' && (normalized.is_empty() || normalized.ends_with('-'))) {
+            normalized.push(c);
+        }
+    }
+    let normalized = normalized.trim_end_matches('-').to_string();
+    let name = match normalized.rsplit_once('/') {
+        Some((_, name)) => name.to_string(),
+        None => normalized.clone(),
+    };
+    std::iter::once(normalized)
+        .chain(PREFIXES.iter().map(|prefix| format!("{}{}", prefix, name)))
+        .find(|candidate| re.is_match(candidate))
+}
+
+impl fmt::Display for Branch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pattern = match &self.pattern {
+            Some(pattern) => fmt_single_parameter(pattern),
+            None => String::from("None"),
+        };
+        let protected = match &self.protected {
+            Some(protected) => format!("{}", Args(protected.clone())),
+            None => String::from("None"),
+        };
+        write!(f, " - Name Pattern: {}\n - Protected: {}\n", pattern, protected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::suggest;
+    use regex::Regex;
+
+    #[test]
+    fn test_suggest() {
+        let re = Regex::new("^(feat|fix)/[a-z0-9-]+$").unwrap();
+        assert_eq!(Some(String::from("feat/my-new-thing")), suggest(&re, "My New_Thing"));
+        assert_eq!(Some(String::from("fix/login")), suggest(&re, "Fix/Login"));
+        assert_eq!(None, suggest(&Regex::new("^JIRA-[0-9]+$").unwrap(), "topic"));
+    }
+}
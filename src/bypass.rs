@@ -0,0 +1,179 @@
+use colored::Colorize;
+use std::{
+    env,
+    sync::OnceLock,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::git;
+
+/**
+ * Names of the checks `FISHERMAN_SKIP` and `FISHERMAN_ONLY` accept
+ */
+pub const CHECKS: [&str; 10] = [
+    "lint",
+    "test",
+    "hygiene",
+    "secrets",
+    "validators",
+    "branch",
+    "commit_message",
+    "post_checkout",
+    "post_merge",
+    "hooks",
+];
+
+/**
+ * Server hooks enforce the policies of a shared repository, a pusher cannot
+ * bypass them
+ */
+const ENFORCED_HOOKS: [&str; 2] = ["pre-receive", "update"];
+
+const AUDIT_FILE: &str = "audit.log";
+
+/**
+ * Whether `FISHERMAN=0` disables every check of `hook`, the bypass is
+ * recorded
+ */
+pub fn disabled(hook: &str) -> bool {
+    if ENFORCED_HOOKS.contains(&hook) || !env::var("FISHERMAN").is_ok_and(|value| value == "0") {
+        return false;
+    }
+    eprintln!("{} Fisherman: {} checks disabled by FISHERMAN=0", "".yellow(), hook);
+    record(hook, "all", "FISHERMAN=0");
+    true
+}
+
+/**
+ * Whether `check` runs in `hook` according to `FISHERMAN_SKIP` and
+ * `FISHERMAN_ONLY`, a skipped check is reported and recorded
+ */
+pub fn allowed(hook: &str, check: &str) -> bool {
+    if ENFORCED_HOOKS.contains(&hook) {
+        return true;
+    }
+    let (skip, only) = selection();
+    let reason = if skip.as_ref().is_some_and(|skip| skip.iter().any(|name| name == check)) {
+        "FISHERMAN_SKIP"
+    } else if only.as_ref().is_some_and(|only| !only.iter().any(|name| name == check)) {
+        "FISHERMAN_ONLY"
+    } else {
+        return true;
+    };
+    eprintln!("{} Fisherman: {} skipped by {}", "".yellow(), check, reason);
+    record(hook, check, reason);
+    false
+}
+
+/**
+ * Names of the configured pre-commit tasks, accepted besides `CHECKS`
+ */
+static TASKS: OnceLock<Vec<String>> = OnceLock::new();
+
+/**
+ * Accept the pre-commit `tasks` in `FISHERMAN_SKIP` and `FISHERMAN_ONLY`,
+ * before the variables are read
+ */
+pub fn register(tasks: Vec<String>) {
+    let _ = TASKS.set(tasks);
+}
+
+/**
+ * Checks of `FISHERMAN_SKIP` and `FISHERMAN_ONLY`
+ */
+type Selection = (Option<Vec<String>>, Option<Vec<String>>);
+
+/**
+ * The selection of the environment, read once
+ */
+fn selection() -> &'static Selection {
+    static SELECTION: OnceLock<Selection> = OnceLock::new();
+    SELECTION.get_or_init(|| (list("FISHERMAN_SKIP"), list("FISHERMAN_ONLY")))
+}
+
+/**
+ * Check names in the comma separated variable `name`, unknown names are
+ * reported
+ */
+fn list(name: &str) -> Option<Vec<String>> {
+    let value = env::var(name).ok().filter(|value| !value.trim().is_empty())?;
+    let names: Vec<String> = value.split(',').map(|check| check.trim().to_string()).collect();
+    let tasks = TASKS.get().map(Vec::as_slice).unwrap_or_default();
+    for unknown in names
+        .iter()
+        .filter(|check| !CHECKS.contains(&check.as_str()) && !tasks.contains(check))
+    {
+        eprintln!(
+            "{} {}: unknown check `{}`, expected one of {}",
+            "[Warning]:".yellow(),
+            name,
+            unknown,
+            CHECKS.join(", ")
+        );
+    }
+    Some(names)
+}
+
+/**
+ * Append the bypass of `check` to `.git/fisherman/audit.log`, one tab
+ * separated line with the time, the user, the hook, the commit, the check
+ * and the reason
+ */
+fn record(hook: &str, check: &str, reason: &str) {
+    let user = git::git(&["var", "GIT_COMMITTER_IDENT"])
+        .and_then(|ident| ident.rfind('>').map(|end| ident[..=end].to_string()))
+        .or_else(|| env::var("USER").ok())
+        .unwrap_or_default();
+    // The commit being created has no id yet, its parent is recorded
+    let commit = git::git(&["rev-parse", "-q", "--verify", "HEAD"]).unwrap_or_else(|| String::from("(root)"));
+    let line = format!("{}\t{}\t{}\t{}\t{}\t{}\n", timestamp(), user, hook, commit, check, reason);
+    if let Err(e) = git::append_data(AUDIT_FILE, &line) {
+        eprintln!("{} cannot record the bypass: {}", "[Warning]:".yellow(), e);
+    }
+}
+
+/**
+ * Current UTC time as `YYYY-MM-DDTHH:MM:SSZ`
+ */
+pub fn timestamp() -> String {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (year, month, day) = civil_date(seconds / 86400);
+    let time = seconds % 86400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+/**
+ * Gregorian date of the day `days` after 1970-01-01
+ */
+fn civil_date(days: u64) -> (u64, u64, u64) {
+    let z = days + 719468;
+    let era = z / 146097;
+    let day_of_era = z % 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::civil_date;
+
+    #[test]
+    fn test_civil_date() {
+        assert_eq!((1970, 1, 1), civil_date(0));
+        assert_eq!((2000, 2, 29), civil_date(11016));
+        assert_eq!((2024, 12, 31), civil_date(20088));
+    }
+}
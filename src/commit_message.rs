@@ -3,16 +3,24 @@ use core::fmt;
 use inquire::{Confirm, Editor, Text};
 use spinners::{Spinner, Spinners, Stream};
 use std::{
-    fs::{File}, io::Read, process::{exit, Command}
+    fs::{self, File}, io::Read, process::exit
 };
 
-use crate::config::{fmt_single_parameter, Cmd};
+use crate::{
+    config::{fmt_single_parameter, Cmd},
+    placeholders::{self, Context},
+};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct CommitMessage {
+    /// Command validating the message, the `message` argument is replaced by it
     pub validation_command: Cmd,
+    /// Open an editor to fix a rejected message
     pub retryable: bool,
+    /// Path of the commit message template
     pub template_path: Option<String>,
 }
 
@@ -34,7 +42,10 @@ impl CommitMessage {
         }
     }
 
-    pub fn run(&self, path: Option<&str>, message: Option<&str>) {
+    /**
+     * Validate the commit message and return it without the comment lines
+     */
+    pub fn run(&self, path: Option<&str>, message: Option<&str>) -> String {
         // Check if the validation command exist
         if !self.validation_command.check() {
             eprintln!(
@@ -58,66 +69,109 @@ impl CommitMessage {
             (None, None) => String::from(""),
         };
 
-        // If the command take some args the message should
-        // be placed in correct position
-        let binding = vec![String::from("message")];
-        let mut args: Vec<String> = match &self.validation_command.args {
-            Some(args) => args.0.clone(),
-            None => binding,
-        };
-
-        let mut replaced = false;
-        args.iter_mut().for_each(|arg| {
-            if arg == "message" {
-                *arg = String::from(&message);
-                replaced = true;
-            }
-        });
-
-        if !replaced {
-            eprintln!("Fisherman Error: `message` args not found.",);
-            exit(1);
-        }
-
         let mut spinner = Spinner::with_stream(
             Spinners::Dots,
             "Fisherman: Commit Message Validation".into(),
             Stream::Stderr,
         );
-        let output = Command::new(&self.validation_command.name)
-            .args(args)
-            .output()
-            .expect("");
-        let success = output.status.success();
+        let (success, sout, serr) = self.validate(&message, path);
 
         if success {
             spinner.stop_and_persist(
                 &format!("{}", "".green()),
                 "Fisherman: Commit Message OK".into(),
             );
+            if !sout.is_empty() {
+                eprintln!("{}", sout);
+            }
         } else {
             spinner.stop_and_persist(
                 &format!("{}", "".red()),
                 "Fisherman: Commit Message Validation Failed".into(),
             );
-            let sout = String::from_utf8(output.stdout).expect("Not UTF-8");
-            let serr = String::from_utf8(output.stderr).expect("Not UTF-8");
             eprintln!("{}", sout);
             eprintln!("{}", serr);
             if self.retryable {
-                self.retry(&message);
+                return self.retry(&message, path);
+            }
+            exit(1);
+        }
+        CommitMessage::format_message(&message)
+    }
+
+    /**
+     * Run the validation command on `message`, read from `message_file` when
+     * known, returning its success and output, or the retry it passed on
+     */
+    pub fn validate(&self, message: &str, message_file: Option<&str>) -> (bool, String, String) {
+        if !self.validation_command.check() {
+            return (
+                false,
+                String::new(),
+                format!("Command `{}` not found, make sure it is installed", self.validation_command.name),
+            );
+        }
+        // If the command take some args the message should
+        // be placed in correct position, unless it reads the file
+        let context = Context {
+            message_file: message_file.map(String::from),
+            ..Context::hook("commit-msg")
+        };
+        let mut args: Vec<String> = match &self.validation_command.args {
+            Some(args) => match placeholders::expand(&args.0, &context) {
+                Ok(args) => args,
+                Err(message) => return (false, String::new(), message),
+            },
+            None => vec![String::from("message")],
+        };
+
+        let mut replaced = false;
+        args.iter_mut().for_each(|arg| {
+            if arg == "message" {
+                *arg = String::from(message);
+                replaced = true;
             }
+        });
+        let reads_file = self
+            .validation_command
+            .args
+            .as_ref()
+            .is_some_and(|args| args.0.iter().any(|arg| arg.contains("{message_file}")));
+
+        if !replaced && !reads_file {
+            eprintln!("Fisherman Error: `message` args not found.",);
             exit(1);
         }
-        println!("{}", CommitMessage::format_message(&message));
+
+        let output = match self
+            .validation_command
+            .retrying(|| self.validation_command.command(None).args(&args).output())
+        {
+            // The output of a pass is not shown, only the retry it passed on
+            Ok((output, retry)) if output.status.success() && retry > 0 => {
+                let retry = self.validation_command.fmt_retry(retry);
+                return (true, format!("{} {}", "[Warning]:".yellow(), retry), String::new());
+            }
+            Ok((output, _)) => output,
+            Err(e) => return (false, String::new(), e.to_string()),
+        };
+        let sout = String::from_utf8_lossy(&output.stdout).to_string();
+        let serr = String::from_utf8_lossy(&output.stderr).to_string();
+        (output.status.success(), sout, serr)
     }
 
-    fn retry(&self, old_message: &str) {
+    fn retry(&self, old_message: &str, path: Option<&str>) -> String {
         let new_message = Editor::new("New Commit Message:")
             .with_predefined_text(old_message)
             .prompt()
             .unwrap();
-        self.run(None, Some(&new_message));
+        // Keep the message file up to date for `{message_file}`
+        if let Some(path) = path {
+            if fs::write(path, &new_message).is_ok() {
+                return self.run(Some(path), None);
+            }
+        }
+        self.run(None, Some(&new_message))
     }
 
     fn format_message(message: &str) -> String {
@@ -0,0 +1,142 @@
+use colored::Colorize;
+use core::fmt;
+use glob::Pattern;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::env;
+
+use crate::git;
+
+/**
+ * Conditions a check runs under, all of them must hold. A check without
+ * conditions always runs.
+ */
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct When {
+    /// Globs the current branch must match one of
+    pub branches: Option<Vec<String>>,
+    /// Globs one of the staged files must match
+    pub files: Option<Vec<String>>,
+    /// Environment variables that must be set
+    pub env: Option<Vec<String>>,
+    /// Environment variables that must not be set
+    pub env_unset: Option<Vec<String>>,
+    /// Run only in CI when true, only outside of CI when false
+    pub ci: Option<bool>,
+    /// Skip the check while concluding a merge
+    pub skip_merge: bool,
+}
+
+impl When {
+    /**
+     * Why the check is skipped, None if it runs
+     */
+    pub fn skip_reason(&self) -> Option<String> {
+        if let Some(patterns) = &self.branches {
+            match git::git(&["symbolic-ref", "--short", "-q", "HEAD"]) {
+                Some(branch) if matches(patterns, &branch) => (),
+                Some(branch) => return Some(format!("branch `{}` does not match {}", branch, patterns.join(", "))),
+                None => return Some(String::from("HEAD is detached")),
+            }
+        }
+        if let Some(patterns) = &self.files {
+            if !git::staged_files().iter().any(|file| matches(patterns, file)) {
+                return Some(format!("no staged file matches {}", patterns.join(", ")));
+            }
+        }
+        if let Some(name) = self.env.iter().flatten().find(|name| !is_set(name)) {
+            return Some(format!("`{}` is not set", name));
+        }
+        if let Some(name) = self.env_unset.iter().flatten().find(|name| is_set(name)) {
+            return Some(format!("`{}` is set", name));
+        }
+        match (self.ci, in_ci()) {
+            (Some(true), false) => return Some(String::from("not running in CI")),
+            (Some(false), true) => return Some(String::from("running in CI")),
+            _ => (),
+        }
+        if self.skip_merge && git::git(&["rev-parse", "-q", "--verify", "MERGE_HEAD"]).is_some() {
+            return Some(String::from("concluding a merge"));
+        }
+        None
+    }
+}
+
+/**
+ * Whether the check `name` runs under `when`, reporting why it is skipped
+ * otherwise
+ */
+pub fn runs(name: &str, when: &Option<When>) -> bool {
+    match when.as_ref().and_then(When::skip_reason) {
+        Some(reason) => {
+            eprintln!("{} Fisherman: {} skipped, {}", "".yellow(), name, reason);
+            false
+        }
+        None => true,
+    }
+}
+
+fn matches(patterns: &[String], value: &str) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| Pattern::new(pattern).is_ok_and(|pattern| pattern.matches(value)))
+}
+
+fn is_set(name: &str) -> bool {
+    env::var_os(name).is_some_and(|value| !value.is_empty())
+}
+
+/**
+ * CI services set `CI`, usually to `true`
+ */
+fn in_ci() -> bool {
+    env::var("CI").is_ok_and(|value| !matches!(value.to_lowercase().as_str(), "" | "0" | "false"))
+}
+
+impl fmt::Display for When {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut conditions: Vec<String> = Vec::new();
+        if let Some(branches) = &self.branches {
+            conditions.push(format!("branch {}", branches.join(", ")));
+        }
+        if let Some(files) = &self.files {
+            conditions.push(format!("files {}", files.join(", ")));
+        }
+        if let Some(env) = &self.env {
+            conditions.push(format!("set {}", env.join(", ")));
+        }
+        if let Some(env_unset) = &self.env_unset {
+            conditions.push(format!("unset {}", env_unset.join(", ")));
+        }
+        match self.ci {
+            Some(true) => conditions.push(String::from("in CI")),
+            Some(false) => conditions.push(String::from("outside CI")),
+            None => (),
+        }
+        if self.skip_merge {
+            conditions.push(String::from("not merging"));
+        }
+        write!(f, "{}", conditions.join("; ").purple())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::When;
+
+    #[test]
+    fn test_skip_reason() {
+        assert_eq!(None, When::default().skip_reason());
+        let when = When {
+            env_unset: Some(vec![String::from("PATH")]),
+            ..When::default()
+        };
+        assert_eq!(Some(String::from("`PATH` is set")), when.skip_reason());
+        let when = When {
+            env: Some(vec![String::from("FISHERMAN_UNSET_VARIABLE")]),
+            ..When::default()
+        };
+        assert_eq!(Some(String::from("`FISHERMAN_UNSET_VARIABLE` is not set")), when.skip_reason());
+    }
+}
@@ -1,26 +1,98 @@
 use colored::Colorize;
 use core::fmt;
-use inquire::{Confirm, MultiSelect, Text};
+use inquire::{MultiSelect, Text};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::{env::current_dir, fs::{self, File}, io::Write, os::unix::fs::PermissionsExt, path::Path, process::Command};
+use sha2::{Digest, Sha256};
+use regex::{Captures, Regex};
+use std::{
+    collections::BTreeMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    thread,
+    time::Duration,
+};
 
-use crate::{commit_message::CommitMessage, flags::Flags, logger::log_error, pre_commit::PreCommit};
+use crate::{branch::Branch, bypass, commit_message::CommitMessage, flags::Flags, git, linter::Lint, hooks::{read_stdin, run_legacy, Hooks}, installer::{self, generate_git_hook, LegacyOrder}, layers::{self, Extend}, logger::log_error, migrations::CONFIG_VERSION, placeholders::{self, Context}, pre_commit::PreCommit, server::Server, sync::{self, SyncAction}};
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// Commands that passed on a retry, in the data directory
+const FLAKY_FILE: &str = "flaky.log";
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
-    pub root_directory: String,
+    /// Layout version of this file, 1 when missing
+    #[schemars(default = "default_version")]
+    pub version: Option<u32>,
+    /// Base configurations merged below this one, the later ones winning
+    pub extends: Option<Vec<Extend>>,
+    /// Directory the checks run from, relative to the repository root
+    pub root_directory: Option<String>,
+    /// Checks run by the `pre-commit` hook
     pub pre_commit: Option<PreCommit>,
+    /// Validation run by the `commit-msg` hook
     pub commit_message: Option<CommitMessage>,
+    /// Commands bound to any git hook, keyed by hook name
+    pub hooks: Option<Hooks>,
+    /// Actions run after a branch checkout when matching files changed
+    pub post_checkout: Option<Vec<SyncAction>>,
+    /// Actions run after a merge when matching files changed
+    pub post_merge: Option<Vec<SyncAction>>,
+    /// Policies enforced on pushes to a shared repository
+    pub server: Option<Server>,
+    /// Branch naming policy and protected branches
+    pub branch: Option<Branch>,
+    /// When pre-existing hooks run with respect to fisherman
+    #[schemars(default = "default_legacy_hooks")]
+    pub legacy_hooks: Option<LegacyOrder>,
+    /// Tracked directory to install the hooks in, set as `core.hooksPath`
+    pub hooks_path: Option<String>,
+    /// Regenerate outdated hooks instead of only warning about them
+    #[schemars(default = "default_auto_refresh")]
+    pub auto_refresh: Option<bool>,
+    /// Hooks of the repository configuration alone, when personal layers
+    /// are merged over it
+    #[serde(skip)]
+    #[schemars(skip)]
+    pub shared_hooks: Option<Vec<String>>,
+}
+
+// Defaults of the optional settings, shown by the JSON schema
+fn default_version() -> Option<u32> {
+    Some(1)
+}
+
+fn default_legacy_hooks() -> Option<LegacyOrder> {
+    Some(LegacyOrder::Before)
+}
+
+fn default_auto_refresh() -> Option<bool> {
+    Some(true)
+}
+
+fn default_retries() -> Option<u32> {
+    Some(0)
 }
 
 impl Config {
     pub fn init() -> Config {
         let mut config: Config = Config {
-            root_directory: String::from(""),
+            version: Some(CONFIG_VERSION),
+            extends: None,
+            root_directory: None,
             pre_commit: None,
             commit_message: None,
+            hooks: None,
+            post_checkout: None,
+            post_merge: None,
+            server: None,
+            branch: None,
+            legacy_hooks: None,
+            hooks_path: None,
+            auto_refresh: None,
+            shared_hooks: None,
         };
-        config.root_directory = String::from(current_dir().unwrap().to_str().unwrap());
         let config_options = vec!["Pre Commit", "Commit Message"];
         let answers = match MultiSelect::new("Select the hooks to enable:", config_options).prompt()
         {
@@ -44,19 +116,32 @@ impl Config {
 impl fmt::Display for Config {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut fmt: String = String::from("");
-        match &self.pre_commit {
-            Some(pre_commit) => {
-                fmt.push_str(&format!("[{}]\n{}", "Pre Commit".bold().blue(), pre_commit))
-            }
-            None => (),
+        if let Some(pre_commit) = &self.pre_commit {
+            fmt.push_str(&format!("[{}]\n{}", "Pre Commit".bold().blue(), pre_commit))
         };
-        match &self.commit_message {
-            Some(commit_message) => fmt.push_str(&format!(
+        if let Some(commit_message) = &self.commit_message {
+            fmt.push_str(&format!(
                 "[{}]\n{}",
                 "Commit Message".bold().blue(),
                 commit_message
-            )),
-            None => (),
+            ))
+        }
+        if let Some(hooks) = &self.hooks {
+            fmt.push_str(&format!("\n[{}]\n{}", "Hooks".bold().blue(), hooks))
+        }
+        if let Some(branch) = &self.branch {
+            fmt.push_str(&format!("\n[{}]\n{}", "Branch".bold().blue(), branch))
+        }
+        if let Some(server) = &self.server {
+            fmt.push_str(&format!("\n[{}]\n{}", "Server".bold().blue(), server))
+        }
+        for (title, actions) in [("Post Checkout", &self.post_checkout), ("Post Merge", &self.post_merge)] {
+            if let Some(actions) = actions {
+                fmt.push_str(&format!("\n[{}]\n", title.bold().blue()));
+                for action in actions {
+                    fmt.push_str(&format!("{}", action));
+                }
+            }
         }
         write!(f, "{}", fmt)
     }
@@ -64,21 +149,310 @@ impl fmt::Display for Config {
 
 impl Config {
     pub fn apply(&self) {
+        layers::exclude_local();
+        let dir = self.install_dir();
+        for hook in self.installed_names() {
+            self.generate(&dir, &hook);
+        }
+    }
+
+    fn generate(&self, dir: &Path, hook: &str) {
+        generate_git_hook(
+            dir,
+            hook,
+            &[Flags::Run.to_string(), String::from(hook)],
+            &self.fingerprint(),
+        );
+    }
+
+    /**
+     * Compare the installed hooks with the configuration and the running
+     * fisherman version, regenerating (or warning about) the missing and
+     * outdated ones. Nothing is done if fisherman has never been applied.
+     */
+    pub fn refresh_hooks(&self) {
+        let Some(dir) = self.hooks_dir() else {
+            return;
+        };
+        let installed = installer::installed_hooks(&dir);
+        if installed.is_empty() {
+            return;
+        }
+        let hooks = self.installed_names();
+        let outdated = installer::outdated_hooks(&dir, &hooks, &self.fingerprint());
+        let removed: Vec<&String> = installed.iter().filter(|hook| !hooks.contains(hook)).collect();
+        if outdated.is_empty() && removed.is_empty() {
+            return;
+        }
+        if !self.auto_refresh.unwrap_or(true) {
+            eprintln!(
+                "{} installed hooks are outdated, run `fisherman {}`",
+                "[Warning]:".yellow(),
+                Flags::ApplyHooks
+            );
+            return;
+        }
+        eprintln!("Fisherman: refreshing outdated hooks");
+        let dir = self.install_dir();
+        for hook in outdated {
+            self.generate(&dir, &hook);
+        }
+        for hook in removed {
+            installer::remove_hook(&dir, hook, false);
+        }
+    }
+
+    /**
+     * Directory the hooks are installed in, without creating it
+     */
+    fn hooks_dir(&self) -> Option<PathBuf> {
+        match &self.hooks_path {
+            Some(hooks_path) => Some(shared_hooks_dir(hooks_path)),
+            None => git::hooks_dir(),
+        }
+    }
+
+    /**
+     * Hash of the fisherman version and the hook names, embedded in the
+     * generated hooks
+     */
+    pub fn fingerprint(&self) -> String {
+        let mut hasher = Sha256::new();
+        // Only what the generated scripts depend on, so that personal
+        // overrides do not rewrite hooks shared through `hooks_path`
+        hasher.update(env!("CARGO_PKG_VERSION"));
+        hasher.update(self.installed_names().join("\n"));
+        let digest = format!("{:x}", hasher.finalize());
+        String::from(&digest[..16])
+    }
+
+    fn lint(&self) -> Option<&Lint> {
+        self.pre_commit.as_ref().and_then(|pre_commit| pre_commit.lint.as_ref())
+    }
+
+    /**
+     * Every command the configuration would run, as approved by `fisherman trust`.
+     * A tracked `hooks_path` is included with the hand-written hooks run
+     * along the generated ones, identified by hook name and content so that
+     * moving them aside on install does not change the approval.
+     */
+    pub fn commands(&self) -> Vec<String> {
+        let mut commands: Vec<String> = self
+            .cmds()
+            .iter()
+            .map(|(origin, command)| format!("{}: {}", origin, command.describe()))
+            .collect();
+        if let Some(hooks_path) = &self.hooks_path {
+            commands.push(format!("hooks_path: {}", hooks_path));
+            let dir = shared_hooks_dir(hooks_path);
+            for (name, path) in installer::chained_hooks(&dir, &self.installed_names()) {
+                let content = fs::read(path).unwrap_or_default();
+                commands.push(format!(
+                    "legacy hook: {}/{} sha256 {:x}",
+                    hooks_path.trim_end_matches('/'),
+                    name,
+                    Sha256::digest(content)
+                ));
+            }
+        }
+        commands
+    }
+
+    /**
+     * Every command of the configuration, with where it is used
+     */
+    pub fn cmds(&self) -> Vec<(String, &Cmd)> {
+        let mut commands: Vec<(String, &Cmd)> = Vec::new();
+        if let Some(pre_commit) = &self.pre_commit {
+            if let Some(lint) = &pre_commit.lint {
+                commands.push((String::from("pre-commit lint"), &lint.command));
+            }
+            if let Some(test) = &pre_commit.test {
+                commands.push((String::from("pre-commit test"), &test.command));
+            }
+            for (name, task) in pre_commit.tasks.iter().flatten() {
+                commands.push((format!("pre-commit task {}", name), &task.command));
+            }
+        }
+        if let Some(commit_message) = &self.commit_message {
+            commands.push((String::from("commit-msg"), &commit_message.validation_command));
+        }
+        for (hook, actions) in [("post-checkout", &self.post_checkout), ("post-merge", &self.post_merge)] {
+            for action in actions.iter().flatten() {
+                if let Some(command) = &action.command {
+                    commands.push((String::from(hook), command));
+                }
+            }
+        }
+        if let Some(hooks) = &self.hooks {
+            for (hook, hook_commands) in &hooks.0 {
+                for command in hook_commands {
+                    commands.push((hook.clone(), command));
+                }
+            }
+        }
+        commands
+    }
+
+    /**
+     * Directory the hooks are installed in. A shared `hooks_path` tracked in
+     * the repository is created and set as `core.hooksPath`.
+     */
+    fn install_dir(&self) -> PathBuf {
+        let hooks_path = match &self.hooks_path {
+            Some(hooks_path) => hooks_path,
+            None => match git::hooks_dir() {
+                Some(dir) => return dir,
+                None => {
+                    log_error("git is not initialized in this directory", true);
+                    unreachable!();
+                }
+            },
+        };
+        let dir = shared_hooks_dir(hooks_path);
+        if let Err(e) = fs::create_dir_all(&dir) {
+            log_error(&format!("Could not create '{}': {}", hooks_path, e), true);
+        }
+        if git::git(&["config", "core.hooksPath", hooks_path]).is_none() {
+            log_error(&format!("Could not set core.hooksPath to '{}'", hooks_path), true);
+        }
+        dir
+    }
+
+    /**
+     * Hooks to install. A shared `hooks_path` only gets the hooks of the
+     * repository configuration, so that personal layers do not change the
+     * files of the team.
+     */
+    fn installed_names(&self) -> Vec<String> {
+        match (&self.hooks_path, &self.shared_hooks) {
+            (Some(_), Some(shared)) => shared.clone(),
+            _ => self.hook_names(),
+        }
+    }
+
+    /**
+     * Names of every git hook bound to at least one check
+     */
+    pub fn hook_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
         if self.pre_commit.is_some() {
-            let code = format!("#!/bin/bash\ncd {}\nfisherman {} \"$1\"\n", &self.root_directory, Flags::PreCommit.to_string());
-            generate_git_hook("pre-commit", &code);
+            names.push(String::from("pre-commit"));
         }
         if self.commit_message.is_some() {
-            let code = format!(
-                "#!/bin/bash\ncd {}\nnew_message=$(fisherman {} $1)\nif [[ $? != 0 ]]; then\nexit 1\nfi\necho $new_message > $1\nexit 0",
-                &self.root_directory, Flags::CommitMessage.to_string()
-            );
-            generate_git_hook("commit-msg", &code);
+            names.push(String::from("commit-msg"));
+        }
+        if self.post_checkout.is_some() {
+            names.push(String::from("post-checkout"));
+        }
+        if self.post_merge.is_some() {
+            names.push(String::from("post-merge"));
+        }
+        if self.server.is_some() {
+            names.push(String::from("pre-receive"));
+        }
+        if self.branch.is_some() {
+            names.extend([String::from("pre-commit"), String::from("pre-push")]);
+        }
+        if let Some(hooks) = &self.hooks {
+            names.extend(hooks.names().cloned());
+        }
+        let mut unique: Vec<String> = Vec::new();
+        for name in names {
+            if !unique.contains(&name) {
+                unique.push(name);
+            }
+        }
+        unique
+    }
+
+    /**
+     * Run the checks bound to `hook` with the arguments git passed to it
+     */
+    pub fn run_hook(&self, hook: &str, git_args: &[String]) {
+        let stdin = read_stdin(hook);
+        // The replaced hook is run from here rather than by the generated
+        // script, so that it only runs once the configuration is trusted
+        let legacy = self.hooks_dir().and_then(|dir| installer::legacy_hook(&dir, hook));
+        let order = self.legacy_hooks.unwrap_or_default();
+        if let Some(legacy) = legacy.as_ref().filter(|_| order == LegacyOrder::Before) {
+            run_legacy(legacy, git_args, &stdin);
+        }
+        self.run_checks(hook, git_args, &stdin);
+        if let Some(legacy) = legacy.as_ref().filter(|_| order == LegacyOrder::After) {
+            run_legacy(legacy, git_args, &stdin);
+        }
+    }
+
+    fn run_checks(&self, hook: &str, git_args: &[String], stdin: &[u8]) {
+        if let Some(tasks) = self.pre_commit.as_ref().and_then(|pre_commit| pre_commit.tasks.as_ref()) {
+            bypass::register(tasks.keys().cloned().collect());
+        }
+        if bypass::disabled(hook) {
+            return;
+        }
+        let allowed = |check: &str| bypass::allowed(hook, check);
+        match hook {
+            "pre-commit" => {
+                if let Some(branch) = self.branch.as_ref().filter(|_| allowed("branch")) {
+                    branch.check_commit();
+                }
+                if let Some(pre_commit) = &self.pre_commit {
+                    pre_commit.run();
+                }
+            }
+            "commit-msg" => {
+                if let Some(commit_message) = self.commit_message.as_ref().filter(|_| allowed("commit_message")) {
+                    // git passes a path relative to the repository root, which
+                    // may differ from `root_directory`
+                    let path = match (git_args.first(), git::toplevel()) {
+                        (Some(path), Some(root)) => root.join(path),
+                        (Some(path), None) => PathBuf::from(path),
+                        (None, _) => {
+                            log_error("commit-msg requires the message file path", true);
+                            unreachable!();
+                        }
+                    };
+                    let message = commit_message.run(Some(&path.to_string_lossy()), None);
+                    if let Err(e) = fs::write(&path, message) {
+                        log_error(&format!("Could not write the commit message: {}", e), true);
+                    }
+                }
+            }
+            "pre-push" => {
+                if let Some(branch) = self.branch.as_ref().filter(|_| allowed("branch")) {
+                    branch.check_push(stdin);
+                }
+            }
+            "post-checkout" => {
+                if let Some(actions) = self.post_checkout.as_ref().filter(|_| allowed("post_checkout")) {
+                    sync::post_checkout(actions, git_args);
+                }
+            }
+            "post-merge" => {
+                if let Some(actions) = self.post_merge.as_ref().filter(|_| allowed("post_merge")) {
+                    sync::post_merge(actions);
+                }
+            }
+            "pre-receive" => {
+                if let Some(server) = &self.server {
+                    server.pre_receive(stdin, self.commit_message.as_ref(), self.lint());
+                }
+            }
+            "update" => {
+                if let Some(server) = &self.server {
+                    server.update(git_args, self.commit_message.as_ref(), self.lint());
+                }
+            }
+            _ => (),
+        }
+        if let Some(hooks) = self.hooks.as_ref().filter(|hooks| hooks.0.contains_key(hook) && allowed("hooks")) {
+            hooks.run(hook, git_args, stdin);
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct Args(pub Vec<String>);
 
 impl fmt::Display for Args {
@@ -95,10 +469,24 @@ impl fmt::Display for Args {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Cmd {
+    /// Program to run, or the command string run by `sh -c` in shell mode
     pub name: String,
+    /// Program arguments, the positional parameters in shell mode
     pub args: Option<Args>,
+    /// Environment variables, `${VAR}` is replaced by the variable of the parent environment
+    pub env: Option<BTreeMap<String, String>>,
+    /// Working directory, relative to the repository root
+    pub cwd: Option<String>,
+    /// Run `name` through `sh -c`, allowing pipelines and redirections
+    pub shell: Option<bool>,
+    /// Times a failing command runs again before it is reported, for flaky commands
+    #[schemars(default = "default_retries")]
+    pub retries: Option<u32>,
+    /// Seconds waited before each retry
+    pub retry_delay: Option<u64>,
 }
 
 impl Cmd {
@@ -106,6 +494,11 @@ impl Cmd {
         let mut command = Cmd {
             name: String::from(""),
             args: None,
+            env: None,
+            cwd: None,
+            shell: None,
+            retries: None,
+            retry_delay: None,
         };
         command.name = Text::new(command_prompt).prompt().unwrap();
         let args = Text::new("Program args:")
@@ -117,9 +510,139 @@ impl Cmd {
         command
     }
 
+    /**
+     * Plain text form of the command line, each argument quoted, with its
+     * working directory, environment and shell
+     */
+    pub fn describe(&self) -> String {
+        let mut description = String::new();
+        if let Some(cwd) = &self.cwd {
+            description.push_str(&format!("cd {:?} && ", cwd));
+        }
+        for (key, value) in self.env.iter().flatten() {
+            description.push_str(&format!("{}={:?} ", key, value));
+        }
+        match self.shell {
+            Some(true) => description.push_str(&format!("sh -c {:?}", self.name)),
+            _ => description.push_str(&self.name),
+        }
+        if let Some(args) = &self.args {
+            for arg in &args.0 {
+                description.push_str(&format!(" {:?}", arg));
+            }
+        }
+        description
+    }
+
+    /**
+     * Arguments with their placeholders expanded for `context`, a wrong
+     * placeholder is reported and fisherman exits
+     */
+    pub fn expanded_args(&self, context: &Context) -> Vec<String> {
+        let args = self.args.as_ref().map(|args| args.0.as_slice()).unwrap_or_default();
+        placeholders::expand_or_exit(args, context)
+    }
+
+    /**
+     * Arguments with their placeholders expanded for `context`, or why a
+     * placeholder is wrong
+     */
+    pub fn expand_args(&self, context: &Context) -> Result<Vec<String>, String> {
+        let args = self.args.as_ref().map(|args| args.0.as_slice()).unwrap_or_default();
+        placeholders::expand(args, context)
+    }
+
+    /**
+     * Process running the command, without its arguments. `cwd` is relative
+     * to `root`, the repository root by default; without `cwd` the process
+     * runs in `root` when given, in the current directory otherwise.
+     */
+    pub fn command(&self, root: Option<&Path>) -> Command {
+        let mut command = match self.shell {
+            Some(true) => {
+                let mut command = Command::new("sh");
+                // `sh` is `$0`, the arguments follow as `$1`...
+                command.arg("-c").arg(&self.name).arg("sh");
+                command
+            }
+            _ => Command::new(&self.name),
+        };
+        for (key, value) in self.env.iter().flatten() {
+            command.env(key, interpolate(value));
+        }
+        let root = root.map(Path::to_path_buf);
+        match (&self.cwd, root) {
+            (Some(cwd), Some(root)) => command.current_dir(root.join(cwd)),
+            (Some(cwd), None) => command.current_dir(git::toplevel().unwrap_or_default().join(cwd)),
+            (None, Some(root)) => command.current_dir(root),
+            (None, None) => &mut command,
+        };
+        command
+    }
+
+    /**
+     * Run the command through `run`, again up to `retries` times while it
+     * fails. Return the last output and the retry it passed on, 0 for a clean
+     * pass. A pass on a retry is recorded in `.git/fisherman/flaky.log`.
+     */
+    pub fn retrying(&self, run: impl FnMut() -> io::Result<Output>) -> io::Result<(Output, u32)> {
+        let (output, retry) = self.attempts(run)?;
+        if output.status.success() && retry > 0 {
+            self.record_flaky(retry);
+        }
+        Ok((output, retry))
+    }
+
+    /**
+     * Run the command through `run` until it passes or ran `retries` times
+     * again, without recording anything
+     */
+    fn attempts(&self, mut run: impl FnMut() -> io::Result<Output>) -> io::Result<(Output, u32)> {
+        let retries = self.retries.unwrap_or(0);
+        let mut retry = 0;
+        loop {
+            let output = run()?;
+            if output.status.success() || retry == retries {
+                return Ok((output, retry));
+            }
+            retry += 1;
+            thread::sleep(Duration::from_secs(self.retry_delay.unwrap_or(0)));
+        }
+    }
+
+    /**
+     * How a command passing on `retry` is reported
+     */
+    pub fn fmt_retry(&self, retry: u32) -> String {
+        format!("passed on retry {}/{}", retry, self.retries.unwrap_or(0))
+    }
+
+    /**
+     * Append the flaky pass to `.git/fisherman/flaky.log`, one tab separated
+     * line with the time, the commit, the retry and the command
+     */
+    fn record_flaky(&self, retry: u32) {
+        let commit = git::git(&["rev-parse", "-q", "--verify", "HEAD"]).unwrap_or_else(|| String::from("(root)"));
+        let line = format!(
+            "{}\t{}\t{}/{}\t{}\n",
+            bypass::timestamp(),
+            commit,
+            retry,
+            self.retries.unwrap_or(0),
+            self.describe()
+        );
+        if let Err(e) = git::append_data(FLAKY_FILE, &line) {
+            eprintln!("{} cannot record the flaky command: {}", "[Warning]:".yellow(), e);
+        }
+    }
+
     pub fn check(&self) -> bool {
+        let program = match self.shell {
+            Some(true) => "sh",
+            _ => &self.name,
+        };
         Command::new("which")
-            .arg(&self.name)
+            .arg(program)
             .output()
             .expect("cannot run which")
             .status
@@ -127,23 +650,82 @@ impl Cmd {
     }
 }
 
-fn generate_git_hook(filename: &str, code: &str) {
-    let path = format!("./.git/hooks/{}", filename);
-
-    if Path::new(&path).exists() && !Confirm::new(&format!("File '{}' already extist, would you like to everwrite?", filename)).with_help_message("y/n").prompt().unwrap() {
-        return;
-    }
-    let mut hook = File::create(&path).unwrap();
-    let metadata = hook.metadata().unwrap();
-    let mut permissions = metadata.permissions();
-    permissions.set_mode(0o751); // Execute/Read/write for owner and read for others.
-    let _ = fs::set_permissions(path, permissions);
-    match hook.write_all(code.as_bytes()) {
-        Ok(_) => eprintln!("Hook '{}' created", filename),
-        Err(e) => log_error(&format!("Could not create Hook '{}': {}", filename, e), true)
+/**
+ * The tracked `hooks_path`, relative to the repository root
+ */
+fn shared_hooks_dir(hooks_path: &str) -> PathBuf {
+    match git::toplevel() {
+        Some(root) => root.join(hooks_path),
+        None => PathBuf::from(hooks_path),
     }
 }
 
+/**
+ * Replace `${VAR}` in `value` with the variable of the environment, unset
+ * variables are empty. `$$` is a literal `$`.
+ */
+pub fn interpolate(value: &str) -> String {
+    let re = Regex::new(r"\$\$|\$\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap();
+    re.replace_all(value, |captures: &Captures| match captures.get(1) {
+        Some(name) => env::var(name.as_str()).unwrap_or_default(),
+        None => String::from("$"),
+    })
+    .to_string()
+}
+
 pub fn fmt_single_parameter(parameter: &str) -> String {
     format!("{}{}{}", "\"".yellow(), parameter.yellow(), "\"".yellow())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{interpolate, Args, Cmd};
+    use std::{collections::BTreeMap, process::Command};
+
+    #[test]
+    fn test_shell_command_description() {
+        let command = Cmd {
+            name: String::from("cargo clippy | tee lint.log"),
+            args: Some(Args(vec![String::from("a b")])),
+            env: Some(BTreeMap::from([(String::from("RUSTFLAGS"), String::from("-Dwarnings"))])),
+            cwd: Some(String::from("sub")),
+            shell: Some(true),
+            retries: None,
+            retry_delay: None,
+        };
+        assert_eq!(
+            "cd \"sub\" && RUSTFLAGS=\"-Dwarnings\" sh -c \"cargo clippy | tee lint.log\" \"a b\"",
+            command.describe()
+        );
+        assert_eq!("${PATH}", interpolate("$${PATH}"));
+        assert_eq!("", interpolate("${FISHERMAN_UNSET_VARIABLE}"));
+    }
+
+    #[test]
+    fn test_retrying() {
+        let command = Cmd {
+            name: String::from("true"),
+            args: None,
+            env: None,
+            cwd: None,
+            shell: None,
+            retries: Some(2),
+            retry_delay: None,
+        };
+        // Run `false` for the first `failures` attempts, then `true`
+        let attempts = |failures: u32| {
+            let mut attempt = 0;
+            let (output, retry) = command
+                .attempts(|| {
+                    attempt += 1;
+                    Command::new(if attempt > failures { "true" } else { "false" }).output()
+                })
+                .unwrap();
+            (output.status.success(), retry, attempt)
+        };
+        assert_eq!((true, 0, 1), attempts(0));
+        assert_eq!((true, 2, 3), attempts(2));
+        assert_eq!((false, 2, 3), attempts(3));
+        assert_eq!("passed on retry 2/2", command.fmt_retry(2));
+    }
+}
@@ -0,0 +1,213 @@
+use colored::Colorize;
+use regex::Regex;
+use std::{fs, path::Path, process::exit};
+
+use crate::{
+    config::Config,
+    layers,
+    migrations::{self, CONFIG_VERSION},
+    placeholders,
+    validators::{toml_error, Location},
+};
+
+/**
+ * Load the configuration at `path` with the personal layers merged over it,
+ * None if there is none.
+ * A configuration that cannot be parsed or has an invalid regex is reported
+ * with its location and fisherman exits.
+ */
+pub fn load(path: &str) -> Option<Config> {
+    let config = layers::resolve(path)?.config;
+    migrations::warn_outdated(path, config.version.unwrap_or(1));
+    // A wrong regex would only fail once a check uses it
+    let problems = regex_problems(&config);
+    for problem in &problems {
+        report_message(path, problem);
+    }
+    if !problems.is_empty() {
+        exit(1);
+    }
+    Some(config)
+}
+
+/**
+ * Parse a configuration of any version, older layouts are migrated in memory
+ */
+pub fn parse(content: &str) -> Result<Config, Location> {
+    let (migrated, from) = migrations::upgrade(content, false)?;
+    toml::from_str::<Config>(&migrated)
+        .map(|config| Config {
+            version: Some(from),
+            ..config
+        })
+        .map_err(|e| match toml_error(&migrated, Some(e)) {
+            Some(mut location) if from < CONFIG_VERSION => {
+                location.message = format!(
+                    "{} (in the configuration migrated from version {}, run `fisherman config migrate`)",
+                    location.message, from
+                );
+                location
+            }
+            Some(location) => location,
+            None => unreachable!(),
+        })
+}
+
+/**
+ * Check what parsing cannot: the commands exist, their placeholders are
+ * known, the task graph has no cycle, the regexes compile and the
+ * referenced files exist. Return the problems found.
+ */
+pub fn check(config: &Config) -> Vec<String> {
+    let mut problems: Vec<String> = Vec::new();
+    for (origin, command) in config.cmds() {
+        if !command.check() {
+            problems.push(format!("{}: command `{}` not found", origin, command.name));
+        }
+        for arg in command.args.iter().flat_map(|args| args.0.iter()) {
+            if let Err(message) = placeholders::check(arg) {
+                problems.push(format!("{}: {}", origin, message));
+            }
+        }
+    }
+
+    if let Some(Err(message)) = config.pre_commit.as_ref().map(|pre_commit| pre_commit.check_tasks()) {
+        problems.push(format!("pre_commit.tasks: {}", message));
+    }
+
+    problems.extend(regex_problems(config));
+
+    let mut files: Vec<(&str, &str)> = Vec::new();
+    if let Some(template_path) = config.commit_message.as_ref().and_then(|c| c.template_path.as_ref()) {
+        files.push(("commit_message.template_path", template_path));
+    }
+    if let Some(allowlist) = config
+        .pre_commit
+        .as_ref()
+        .and_then(|pre_commit| pre_commit.secrets.as_ref())
+        .and_then(|secrets| secrets.allowlist.as_ref())
+    {
+        files.push(("pre_commit.secrets.allowlist", allowlist));
+    }
+    for (key, file) in files {
+        if !Path::new(file).exists() {
+            problems.push(format!("{}: file '{}' not found", key, file));
+        }
+    }
+    problems
+}
+
+/**
+ * The regexes of the configuration that do not compile
+ */
+fn regex_problems(config: &Config) -> Vec<String> {
+    let mut problems: Vec<String> = Vec::new();
+    let mut regexes: Vec<(String, &str)> = Vec::new();
+    if let Some(lint) = config.pre_commit.as_ref().and_then(|pre_commit| pre_commit.lint.as_ref()) {
+        regexes.push((String::from("pre_commit.lint.file_ext"), &lint.file_ext));
+    }
+    if let Some(pattern) = config.branch.as_ref().and_then(|branch| branch.pattern.as_ref()) {
+        regexes.push((String::from("branch.pattern"), pattern));
+    }
+    for (section, actions) in [("post_checkout", &config.post_checkout), ("post_merge", &config.post_merge)] {
+        for (index, action) in actions.iter().flatten().enumerate() {
+            regexes.push((format!("{}[{}].files", section, index), &action.files));
+        }
+    }
+    for (key, regex) in regexes {
+        if let Err(e) = Regex::new(regex) {
+            problems.push(format!("{}: invalid regex `{}`: {}", key, regex, e));
+        }
+    }
+    problems
+}
+
+/**
+ * `fisherman config validate`: report every problem of the configuration
+ */
+pub fn validate(path: &str) {
+    if let Err(e) = fs::metadata(path) {
+        eprintln!("{}{}: {}", "[Error]:".red(), path, e);
+        exit(1);
+    }
+    let config = layers::resolve(path).unwrap().config;
+    let problems = check(&config);
+    if problems.is_empty() {
+        eprintln!("{} is valid", path);
+        return;
+    }
+    for problem in problems {
+        eprintln!("{}{}: {}", "[Error]:".red(), path, problem);
+    }
+    exit(1);
+}
+
+/**
+ * `fisherman config schema`: print the JSON Schema of the configuration,
+ * for editors validating and completing `.fisherman.toml`
+ */
+pub fn schema() {
+    let schema = schemars::schema_for!(Config);
+    println!("{}", serde_json::to_string_pretty(&schema).unwrap());
+}
+
+pub fn report(path: &str, error: &Location) {
+    report_message(&format!("{}:{}:{}", path, error.line, error.column), &error.message);
+}
+
+/**
+ * Report a problem of the configuration found at `origin`
+ */
+pub fn report_message(origin: &str, message: &str) {
+    eprintln!("{}{}: {}", "[Error]:".red(), origin, message.trim_end());
+    if let Some(suggestion) = suggest(message) {
+        eprintln!("  did you mean `{}`?", suggestion.bold());
+    }
+}
+
+/**
+ * Closest expected key for an `unknown field` error
+ */
+fn suggest(message: &str) -> Option<String> {
+    let re = Regex::new(r"unknown field `([^`]+)`, expected (.*)").unwrap();
+    let captures = re.captures(message)?;
+    let unknown = &captures[1];
+    let expected = Regex::new(r"`([^`]+)`").unwrap();
+    expected
+        .captures_iter(&captures[2])
+        .map(|key| key[1].to_string())
+        .map(|key| (distance(unknown, &key), key))
+        .filter(|(distance, key)| *distance <= key.len().max(unknown.len()) / 2)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, key)| key)
+}
+
+/**
+ * Levenshtein distance between `a` and `b`
+ */
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, suggest};
+
+    #[test]
+    fn test_unknown_key_suggestion() {
+        let error = parse("root_directory = \".\"\n[pre_comit]\n").err().unwrap();
+        assert_eq!(2, error.line);
+        assert_eq!(Some(String::from("pre_commit")), suggest(&error.message));
+        assert_eq!(None, suggest("unknown field `zzzzzzzz`, expected `a`, `b`"));
+    }
+}
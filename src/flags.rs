@@ -1,4 +1,4 @@
-use std::str::FromStr;
+use std::{fmt, str::FromStr};
 
 pub enum Flags {
     Init,
@@ -7,6 +7,10 @@ pub enum Flags {
     PreCommit,
     CommitMessage,
     ApplyHooks,
+    Run,
+    Uninstall,
+    Trust,
+    Config,
     InvalidFlag,
     Help,
     ShowConfig,
@@ -22,6 +26,10 @@ impl FromStr for Flags {
             "-c" | "--pre-commit" => Ok(Flags::PreCommit),
             "-m" | "--commit-message" => Ok(Flags::CommitMessage),
             "-a" | "--apply-hooks" => Ok(Flags::ApplyHooks),
+            "run" => Ok(Flags::Run),
+            "uninstall" => Ok(Flags::Uninstall),
+            "trust" => Ok(Flags::Trust),
+            "config" => Ok(Flags::Config),
             "-h" | "--help" => Ok(Flags::Help),
             "-s" | "--show-config" => Ok(Flags::ShowConfig),
             _ => Ok(Flags::InvalidFlag),
@@ -29,8 +37,8 @@ impl FromStr for Flags {
     }
 }
 
-impl ToString for Flags {
-    fn to_string(&self) -> String {
+impl fmt::Display for Flags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let s = match *self {
             Flags::Init => "-i",
             Flags::Lint => "-l",
@@ -38,10 +46,14 @@ impl ToString for Flags {
             Flags::PreCommit => "-c",
             Flags::CommitMessage => "-m",
             Flags::ApplyHooks => "-a",
+            Flags::Run => "run",
+            Flags::Uninstall => "uninstall",
+            Flags::Trust => "trust",
+            Flags::Config => "config",
             Flags::InvalidFlag => "INVALID FLAGS",
             Flags::Help => "-h",
             Flags::ShowConfig => "-s",
         };
-        String::from(s)
+        write!(f, "{}", s)
     }
 }
@@ -0,0 +1,102 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/**
+ * Run git with `args` and return its trimmed stdout, None if git failed
+ */
+pub fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim_end().to_string())
+}
+
+/**
+ * Root of the current working tree
+ */
+pub fn toplevel() -> Option<PathBuf> {
+    git(&["rev-parse", "--show-toplevel"]).map(PathBuf::from)
+}
+
+/**
+ * The `.git` directory shared by every worktree of the repository
+ */
+pub fn common_dir() -> Option<PathBuf> {
+    git(&["rev-parse", "--git-common-dir"]).map(|dir| absolute(Path::new(&dir)))
+}
+
+/**
+ * Directory git runs hooks from, `core.hooksPath` included
+ */
+pub fn hooks_dir() -> Option<PathBuf> {
+    if let Some(hooks_path) = git(&["config", "core.hooksPath"]) {
+        // A relative core.hooksPath is relative to the working tree root
+        let hooks_path = PathBuf::from(hooks_path);
+        return match hooks_path.is_absolute() {
+            true => Some(hooks_path),
+            false => toplevel().map(|root| root.join(hooks_path)),
+        };
+    }
+    git(&["rev-parse", "--git-path", "hooks"]).map(|dir| absolute(Path::new(&dir)))
+}
+
+/**
+ * Files added, copied, modified or renamed by the staged changes, relative to
+ * the repository root
+ */
+pub fn staged_files() -> Vec<String> {
+    match git(&["diff", "--cached", "--name-only", "--diff-filter=ACMR", "-z"]) {
+        Some(names) => names
+            .split('\0')
+            .filter(|name| !name.is_empty())
+            .map(String::from)
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+/**
+ * Content of `path` in the index
+ */
+pub fn staged_content(path: &str) -> Option<Vec<u8>> {
+    let output = Command::new("git")
+        .args(["cat-file", "blob", &format!(":{}", path)])
+        .output()
+        .ok()?;
+    output.status.success().then_some(output.stdout)
+}
+
+/**
+ * Where fisherman keeps its own data, `.git/fisherman/`
+ */
+pub fn data_dir() -> Option<PathBuf> {
+    common_dir().map(|dir| dir.join("fisherman"))
+}
+
+/**
+ * Append `line` to `file` of the data directory, nothing is written outside
+ * of a repository
+ */
+pub fn append_data(file: &str, line: &str) -> io::Result<()> {
+    let Some(dir) = data_dir() else {
+        return Ok(());
+    };
+    fs::create_dir_all(&dir)?;
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(file))?
+        .write_all(line.as_bytes())
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    match path.canonicalize() {
+        Ok(path) => path,
+        Err(_) => path.to_path_buf(),
+    }
+}
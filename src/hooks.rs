@@ -0,0 +1,175 @@
+use colored::Colorize;
+use core::fmt;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use spinners::{Spinner, Spinners, Stream};
+use std::{
+    collections::BTreeMap,
+    io::{self, IsTerminal, Read, Write},
+    path::Path,
+    process::{exit, Command, Stdio},
+};
+
+use crate::{
+    config::{fmt_single_parameter, Cmd},
+    git,
+    logger::log_error,
+    placeholders::Context,
+};
+
+/**
+ * Commands bound to arbitrary git hooks, keyed by the hook name
+ * (e.g. `pre-push`, `post-rewrite`).
+ */
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
+pub struct Hooks(pub BTreeMap<String, Vec<Cmd>>);
+
+impl Hooks {
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.0.keys()
+    }
+
+    /**
+     * Run every command bound to `hook`, forwarding the arguments and the
+     * stdin git passed to the hook. Exit on the first failing command.
+     */
+    pub fn run(&self, hook: &str, git_args: &[String], stdin: &[u8]) {
+        let commands = match self.0.get(hook) {
+            Some(commands) => commands,
+            None => return,
+        };
+        for command in commands {
+            if !command.check() {
+                log_error(
+                    &format!(
+                        "Command `{}` not found, make sure it is installed",
+                        &command.name
+                    ),
+                    true,
+                );
+            }
+            let (success, sout, serr) = run_command(hook, command, git_args, stdin);
+            if !success {
+                eprintln!("{}", sout);
+                eprintln!("{}", serr);
+                exit(1);
+            }
+        }
+    }
+}
+
+pub fn run_command(hook: &str, command: &Cmd, git_args: &[String], stdin: &[u8]) -> (bool, String, String) {
+    let args = command.expanded_args(&Context::hook(hook));
+
+    let mut spinner = Spinner::with_stream(
+        Spinners::Dots,
+        format!("Fisherman: {} `{}` in progress", hook, command.name),
+        Stream::Stderr,
+    );
+    let (output, retry) = command
+        .retrying(|| {
+            let mut child = command
+                .command(None)
+                .args(&args)
+                .args(git_args)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()?;
+            if let Some(mut child_stdin) = child.stdin.take() {
+                // The command may exit without consuming its input, which is fine.
+                let _ = child_stdin.write_all(stdin);
+            }
+            child.wait_with_output()
+        })
+        .expect("");
+    let success = output.status.success();
+
+    if success && retry > 0 {
+        spinner.stop_and_persist(
+            &format!("{}", "".yellow()),
+            format!("Fisherman: {} `{}` {}", hook, command.name, command.fmt_retry(retry)),
+        );
+    } else if success {
+        spinner.stop_and_persist(
+            &format!("{}", "".green()),
+            format!("Fisherman: {} `{}` OK", hook, command.name),
+        );
+    } else {
+        spinner.stop_and_persist(
+            &format!("{}", "".red()),
+            format!("Fisherman: {} `{}` Failed", hook, command.name),
+        );
+    }
+    let sout = String::from_utf8(output.stdout).expect("Not UTF-8");
+    let serr = String::from_utf8(output.stderr).expect("Not UTF-8");
+    (success, sout, serr)
+}
+
+/**
+ * Run the hook `path` replaced by fisherman from the repository root with
+ * the hook arguments and stdin, exit with its code when it fails
+ */
+pub fn run_legacy(path: &Path, git_args: &[String], stdin: &[u8]) {
+    let mut command = Command::new(path);
+    if let Some(root) = git::toplevel() {
+        command.current_dir(root);
+    }
+    let status = command.args(git_args).stdin(Stdio::piped()).spawn().and_then(|mut child| {
+        if let Some(mut child_stdin) = child.stdin.take() {
+            // The hook may exit without consuming its input, which is fine.
+            let _ = child_stdin.write_all(stdin);
+        }
+        child.wait()
+    });
+    match status {
+        Ok(status) if status.success() => (),
+        Ok(status) => exit(status.code().unwrap_or(1)),
+        Err(e) => log_error(&format!("Could not run '{}': {}", path.display(), e), true),
+    }
+}
+
+/// Hooks git feeds on stdin, the others may inherit a stdin that never closes
+const STDIN_HOOKS: [&str; 6] = [
+    "pre-push",
+    "pre-receive",
+    "post-receive",
+    "post-rewrite",
+    "reference-transaction",
+    "proc-receive",
+];
+
+/**
+ * Read what git wrote on the hook stdin (e.g. the refs list of `pre-push`).
+ * Nothing is read for hooks without input or when fisherman is run by hand
+ * from a terminal.
+ */
+pub fn read_stdin(hook: &str) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    let stdin = io::stdin();
+    if STDIN_HOOKS.contains(&hook) && !stdin.is_terminal() {
+        let _ = stdin.lock().read_to_end(&mut buffer);
+    }
+    buffer
+}
+
+impl fmt::Display for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut fmt: String = String::from("");
+        for (hook, commands) in &self.0 {
+            fmt.push_str(&format!(" {}\n", format!("{}:", hook).bold().green()));
+            for command in commands {
+                let args = match &command.args {
+                    Some(args) => format!("{}", args),
+                    None => String::from("None"),
+                };
+                fmt.push_str(&format!(
+                    "  - Command: {}\n  - Command Args: {}\n",
+                    fmt_single_parameter(&command.name),
+                    args
+                ));
+            }
+        }
+        write!(f, "{}", fmt)
+    }
+}
@@ -0,0 +1,324 @@
+use colored::Colorize;
+use core::fmt;
+use inquire::{Confirm, MultiSelect, Text};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+};
+
+use crate::{conditions::When, git, logger::log_error};
+
+const CHECKS: [&str; 8] = [
+    "Trailing whitespace",
+    "Missing final newline",
+    "CRLF line endings",
+    "Merge conflict markers",
+    "Byte-order marks",
+    "Large files",
+    "Filename case collisions",
+    "Broken symlinks",
+];
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+const SYMLINK_MODE: &str = "120000";
+
+/**
+ * File checks run natively on the staged files, no external tool needed.
+ * With `fix` the whitespace, newline and encoding problems are corrected and
+ * restaged when the file has no unstaged changes.
+ */
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Hygiene {
+    /// Reject trailing whitespace
+    pub trailing_whitespace: bool,
+    /// Reject files not ending with a newline
+    pub final_newline: bool,
+    /// Reject CRLF line endings
+    pub crlf: bool,
+    /// Reject merge conflict markers
+    pub conflict_markers: bool,
+    /// Reject byte-order marks
+    pub bom: bool,
+    /// Maximum size of a staged file in bytes
+    pub max_file_size: Option<u64>,
+    /// Reject file names differing only by case
+    pub case_collisions: bool,
+    /// Reject symlinks to missing files
+    pub broken_symlinks: bool,
+    /// Fix whitespace, newline and encoding problems and restage the files
+    pub fix: bool,
+    /// Conditions the check runs under
+    pub when: Option<When>,
+    /// Checks and tasks that must succeed first
+    pub depends_on: Option<Vec<String>>,
+}
+
+/**
+ * A staged file as listed by `git ls-files --stage`
+ */
+struct StagedFile {
+    mode: String,
+    path: String,
+}
+
+impl Hygiene {
+    pub fn init() -> Hygiene {
+        let mut hygiene = Hygiene::default();
+        let answers = match MultiSelect::new("Select the checks to enable:", CHECKS.to_vec()).prompt() {
+            Ok(res) => res,
+            Err(e) => {
+                log_error(&e.to_string(), true);
+                unreachable!();
+            }
+        };
+        for check in answers {
+            match check {
+                "Trailing whitespace" => hygiene.trailing_whitespace = true,
+                "Missing final newline" => hygiene.final_newline = true,
+                "CRLF line endings" => hygiene.crlf = true,
+                "Merge conflict markers" => hygiene.conflict_markers = true,
+                "Byte-order marks" => hygiene.bom = true,
+                "Large files" => {
+                    let size = Text::new("Maximum file size in bytes:").prompt().unwrap();
+                    hygiene.max_file_size = size.trim().parse().ok();
+                }
+                "Filename case collisions" => hygiene.case_collisions = true,
+                "Broken symlinks" => hygiene.broken_symlinks = true,
+                _ => (),
+            }
+        }
+        hygiene.fix = Confirm::new("Fix problems automatically?")
+            .with_help_message("y/n")
+            .prompt()
+            .unwrap();
+        hygiene
+    }
+
+    /**
+     * Check the staged files, return whether they are clean and the report
+     */
+    pub fn execute(&self) -> (bool, String) {
+        let (problems, fixed) = self.check(&staged_files());
+        let report: Vec<String> = fixed.iter().map(|file| format!("Fixed {}", file)).chain(problems.iter().cloned()).collect();
+        (problems.is_empty(), report.join("\n"))
+    }
+
+    /**
+     * Problems found in `files` and the files that were fixed
+     */
+    fn check(&self, files: &[StagedFile]) -> (Vec<String>, Vec<String>) {
+        let mut problems: Vec<String> = Vec::new();
+        let mut fixed: Vec<String> = Vec::new();
+        for file in files {
+            if file.mode == SYMLINK_MODE {
+                if self.broken_symlinks && is_broken_symlink(&file.path) {
+                    problems.push(format!("{}: broken symlink", file.path));
+                }
+                continue;
+            }
+            let content = match git::staged_content(&file.path) {
+                Some(content) => content,
+                None => continue,
+            };
+            if let Some(max_file_size) = self.max_file_size {
+                if content.len() as u64 > max_file_size {
+                    problems.push(format!("{}: {} bytes exceed the {} bytes limit", file.path, content.len(), max_file_size));
+                }
+            }
+            if content.contains(&0) {
+                // Binary file, the text checks do not apply
+                continue;
+            }
+            let mut file_problems = self.check_content(&content);
+            if self.fix && !file_problems.fixable.is_empty() && self.fix_file(&file.path, &content) {
+                fixed.push(file.path.clone());
+                file_problems.fixable.clear();
+            }
+            for problem in file_problems.fixable.into_iter().chain(file_problems.other) {
+                problems.push(format!("{}:{}", file.path, problem));
+            }
+        }
+        if self.case_collisions {
+            problems.extend(case_collisions(files));
+        }
+        (problems, fixed)
+    }
+
+    fn check_content(&self, content: &[u8]) -> ContentProblems {
+        let mut problems = ContentProblems::default();
+        if self.bom && content.starts_with(BOM) {
+            problems.fixable.push(String::from("1: byte-order mark"));
+        }
+        if self.final_newline && !content.is_empty() && !content.ends_with(b"\n") {
+            problems.fixable.push(String::from(" missing final newline"));
+        }
+        let text = String::from_utf8_lossy(content);
+        for (index, line) in text.split('\n').enumerate() {
+            let number = index + 1;
+            if self.crlf && line.ends_with('\r') {
+                problems.fixable.push(format!("{}: CRLF line ending", number));
+            }
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            if self.trailing_whitespace && line.ends_with([' ', '\t']) {
+                problems.fixable.push(format!("{}: trailing whitespace", number));
+            }
+            if self.conflict_markers && (line.starts_with("<<<<<<< ") || line.starts_with(">>>>>>> ")) {
+                problems.other.push(format!("{}: merge conflict marker", number));
+            }
+        }
+        problems
+    }
+
+    /**
+     * Rewrite and restage `path`, unless its working tree copy differs from
+     * the staged one
+     */
+    fn fix_file(&self, path: &str, content: &[u8]) -> bool {
+        let path = worktree_path(path);
+        match fs::read(&path) {
+            Ok(working) if working == content => (),
+            _ => return false,
+        }
+        let fixed = self.fixed_content(content);
+        fs::write(&path, fixed).is_ok() && git::git(&["--literal-pathspecs", "add", "--", &path.to_string_lossy()]).is_some()
+    }
+
+    fn fixed_content(&self, content: &[u8]) -> Vec<u8> {
+        let content = match self.bom {
+            true => content.strip_prefix(BOM).unwrap_or(content),
+            false => content,
+        };
+        // Bytes rather than text, a file in another encoding keeps its content
+        let mut lines: Vec<Vec<u8>> = Vec::new();
+        for line in content.split(|byte| *byte == b'\n') {
+            let mut line = line.to_vec();
+            let crlf = line.ends_with(b"\r");
+            if crlf {
+                line.pop();
+            }
+            if self.trailing_whitespace {
+                while line.ends_with(b" ") || line.ends_with(b"\t") {
+                    line.pop();
+                }
+            }
+            if crlf && !self.crlf {
+                line.push(b'\r');
+            }
+            lines.push(line);
+        }
+        let mut fixed = lines.join(&b'\n');
+        if self.final_newline && !fixed.is_empty() && !fixed.ends_with(b"\n") {
+            fixed.push(b'\n');
+        }
+        fixed
+    }
+}
+
+#[derive(Default)]
+struct ContentProblems {
+    fixable: Vec<String>,
+    other: Vec<String>,
+}
+
+fn staged_files() -> Vec<StagedFile> {
+    let names = git::staged_files();
+    if names.is_empty() {
+        return Vec::new();
+    }
+    // The names are paths, not patterns: `a[1].txt` must not match `a1.txt`
+    let mut args = vec!["--literal-pathspecs", "ls-files", "--stage", "-z", "--"];
+    args.extend(names.iter().map(String::as_str));
+    let stage = git::git(&args).unwrap_or_default();
+    stage
+        .split('\0')
+        .filter_map(|line| {
+            let (info, path) = line.split_once('\t')?;
+            Some(StagedFile {
+                mode: info.split_whitespace().next()?.to_string(),
+                path: path.to_string(),
+            })
+        })
+        .collect()
+}
+
+/**
+ * Staged paths are relative to the repository root, which may not be the
+ * current directory
+ */
+fn worktree_path(path: &str) -> PathBuf {
+    match git::toplevel() {
+        Some(root) => root.join(path),
+        None => PathBuf::from(path),
+    }
+}
+
+fn is_broken_symlink(path: &str) -> bool {
+    let path = worktree_path(path);
+    path.symlink_metadata().is_ok() && fs::metadata(path).is_err()
+}
+
+/**
+ * Staged files whose name differs only by case from another tracked file,
+ * which breaks checkouts on case-insensitive filesystems
+ */
+fn case_collisions(files: &[StagedFile]) -> Vec<String> {
+    let tracked = git::git(&["ls-files", "-z"]).unwrap_or_default();
+    let mut by_name: HashMap<String, Vec<&str>> = HashMap::new();
+    for path in tracked.split('\0').filter(|path| !path.is_empty()) {
+        by_name.entry(path.to_lowercase()).or_default().push(path);
+    }
+    let mut problems = Vec::new();
+    for file in files {
+        if let Some(paths) = by_name.get(&file.path.to_lowercase()) {
+            if paths.len() > 1 {
+                problems.push(format!("{}: case collision with {}", file.path, paths.iter().filter(|p| **p != file.path).cloned().collect::<Vec<_>>().join(", ")));
+            }
+        }
+    }
+    problems
+}
+
+impl fmt::Display for Hygiene {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let enabled = [
+            (self.trailing_whitespace, CHECKS[0]),
+            (self.final_newline, CHECKS[1]),
+            (self.crlf, CHECKS[2]),
+            (self.conflict_markers, CHECKS[3]),
+            (self.bom, CHECKS[4]),
+            (self.max_file_size.is_some(), CHECKS[5]),
+            (self.case_collisions, CHECKS[6]),
+            (self.broken_symlinks, CHECKS[7]),
+        ];
+        let checks: Vec<&str> = enabled.iter().filter(|(on, _)| *on).map(|(_, name)| *name).collect();
+        write!(
+            f,
+            "  - Checks: {}\n  - Fix: {}\n",
+            checks.join(", ").yellow(),
+            self.fix.to_string().purple()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Hygiene;
+
+    #[test]
+    fn test_fixed_content() {
+        let hygiene = Hygiene {
+            trailing_whitespace: true,
+            final_newline: true,
+            crlf: true,
+            bom: true,
+            ..Hygiene::default()
+        };
+        let fixed = hygiene.fixed_content(b"\xEF\xBB\xBFfoo  \r\nbar\t");
+        assert_eq!(b"foo\nbar\n".to_vec(), fixed);
+        // Latin-1 is not UTF-8 and must not be replaced
+        assert_eq!(b"caf\xe9\n".to_vec(), hygiene.fixed_content(b"caf\xe9  "));
+    }
+}
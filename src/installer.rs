@@ -0,0 +1,278 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+use crate::{git, logger::log_error};
+
+/// Comment placed in every generated hook, used to tell them apart from hooks
+/// written by hand or by other tools.
+pub const HOOK_MARKER: &str = "# Generated by fisherman, do not edit.";
+const FINGERPRINT_PREFIX: &str = "# fisherman-fingerprint: ";
+const LEGACY_SUFFIX: &str = ".fisherman-legacy";
+
+/**
+ * When a pre-existing hook runs with respect to fisherman's checks
+ */
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LegacyOrder {
+    #[default]
+    Before,
+    After,
+}
+
+/**
+ * Install the hook `filename` in `dir`, calling fisherman with `args`.
+ * The `fingerprint` of the configuration is embedded to detect stale hooks.
+ * A hook not generated by fisherman is moved to `<hook>.fisherman-legacy`,
+ * which fisherman runs once the configuration is trusted, so adopting
+ * fisherman does not lose it.
+ */
+pub fn generate_git_hook(dir: &Path, filename: &str, args: &[String], fingerprint: &str) {
+    let path = dir.join(filename);
+    let legacy = legacy_path(dir, filename);
+
+    if path.exists() && !is_generated(&path) {
+        if legacy.exists() {
+            log_error(
+                &format!("Hook '{}' not created: both '{}' and '{}' exist", filename, path.display(), legacy.display()),
+                false,
+            );
+            return;
+        }
+        if let Err(e) = fs::rename(&path, &legacy) {
+            log_error(&format!("Could not move Hook '{}': {}", filename, e), true);
+        }
+        eprintln!("Existing hook '{}' moved to '{}'", filename, legacy.display());
+    }
+    let code = hook_script(args, fingerprint);
+    // Write aside and rename, a hook refreshed while it runs keeps reading
+    // its old content.
+    let tmp = dir.join(format!(".{}.fisherman-tmp", filename));
+    let written = fs::write(&tmp, code)
+        .and_then(|_| fs::set_permissions(&tmp, fs::Permissions::from_mode(0o751))) // Execute/Read/write for owner and read for others.
+        .and_then(|_| fs::rename(&tmp, &path));
+    match written {
+        Ok(_) => eprintln!("Hook '{}' created", filename),
+        Err(e) => log_error(&format!("Could not create Hook '{}': {}", filename, e), true)
+    }
+}
+
+/**
+ * Names of the hooks generated by fisherman in `dir`
+ */
+pub fn installed_hooks(dir: &Path) -> Vec<String> {
+    let mut hooks: Vec<String> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file() && is_generated(path))
+            .map(|path| path.file_name().unwrap().to_string_lossy().to_string())
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    hooks.sort();
+    hooks
+}
+
+/**
+ * Hand-written hooks of `dir` run along the generated `hooks`, by hook
+ * name: the ones already moved aside and the ones installing would move
+ */
+pub fn chained_hooks(dir: &Path, hooks: &[String]) -> Vec<(String, PathBuf)> {
+    let mut chained: Vec<(String, PathBuf)> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let name = entry.file_name().to_string_lossy().strip_suffix(LEGACY_SUFFIX)?.to_string();
+                Some((name, entry.path()))
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    for hook in hooks {
+        let path = dir.join(hook);
+        if path.is_file() && !is_generated(&path) && !legacy_path(dir, hook).exists() {
+            chained.push((hook.clone(), path));
+        }
+    }
+    chained.sort();
+    chained
+}
+
+/**
+ * The hook `name` of `dir` replaced by the generated one, if any
+ */
+pub fn legacy_hook(dir: &Path, name: &str) -> Option<PathBuf> {
+    Some(legacy_path(dir, name)).filter(|path| path.is_file())
+}
+
+/**
+ * Hooks among `hooks` that are missing from `dir` or were generated with
+ * another fingerprint
+ */
+pub fn outdated_hooks(dir: &Path, hooks: &[String], fingerprint: &str) -> Vec<String> {
+    hooks
+        .iter()
+        .filter(|hook| fingerprint_of(&dir.join(hook)).as_deref() != Some(fingerprint))
+        .cloned()
+        .collect()
+}
+
+/**
+ * Remove the generated hook `name` from `dir` and restore the hook it replaced
+ */
+pub fn remove_hook(dir: &Path, name: &str, dry_run: bool) {
+    let path = dir.join(name);
+    let prefix = if dry_run { "Would remove" } else { "Removed" };
+    if !dry_run {
+        if let Err(e) = fs::remove_file(&path) {
+            log_error(&format!("Could not remove Hook '{}': {}", name, e), false);
+            return;
+        }
+    }
+    eprintln!("{} hook '{}'", prefix, name);
+
+    let legacy = legacy_path(dir, name);
+    if legacy.exists() {
+        if !dry_run {
+            if let Err(e) = fs::rename(&legacy, &path) {
+                log_error(&format!("Could not restore Hook '{}': {}", name, e), false);
+                return;
+            }
+        }
+        let restore = if dry_run { "Would restore" } else { "Restored" };
+        eprintln!("{} hook '{}' from '{}'", restore, name, legacy.display());
+    }
+}
+
+/**
+ * Build a POSIX `sh` hook script calling fisherman with `args` followed by
+ * the hook arguments. The repository root is found by git at runtime so the
+ * script does not depend on where the repository lives.
+ */
+fn hook_script(args: &[String], fingerprint: &str) -> String {
+    let args: Vec<String> = args.iter().map(|arg| quote(arg)).collect();
+    let lines: Vec<String> = vec![
+        String::from("#!/bin/sh"),
+        String::from(HOOK_MARKER),
+        format!("{}{}", FINGERPRINT_PREFIX, fingerprint),
+        String::from("fisherman=\"${FISHERMAN_BIN:-fisherman}\""),
+        String::from("if ! command -v \"$fisherman\" >/dev/null 2>&1; then"),
+        String::from("  echo \"fisherman: '$fisherman' not found in PATH, install it or set FISHERMAN_BIN\" >&2"),
+        String::from("  exit 1"),
+        String::from("fi"),
+        format!("exec \"$fisherman\" {} \"$@\"", args.join(" ")),
+    ];
+    lines.join("\n") + "\n"
+}
+
+/**
+ * Quote `value` for a POSIX shell
+ */
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/**
+ * Remove the hooks generated by fisherman and restore the ones they replaced.
+ * With `purge` the cached data under `.git/fisherman/` is deleted as well,
+ * with `dry_run` the changes are only listed. `core.hooksPath` is unset
+ * when it is the configured `hooks_path`.
+ */
+pub fn uninstall(purge: bool, dry_run: bool, hooks_path: Option<&str>) {
+    let dir = match git::hooks_dir() {
+        Some(dir) => dir,
+        None => {
+            log_error("git is not initialized in this directory", true);
+            unreachable!();
+        }
+    };
+    if !dir.is_dir() {
+        log_error(&format!("Could not read '{}'", dir.display()), true);
+    }
+    for name in installed_hooks(&dir) {
+        remove_hook(&dir, &name, dry_run);
+    }
+
+    let prefix = if dry_run { "Would remove" } else { "Removed" };
+    if let Some(data_dir) = git::data_dir().filter(|dir| purge && dir.exists()) {
+        if !dry_run {
+            if let Err(e) = fs::remove_dir_all(&data_dir) {
+                log_error(&format!("Could not remove '{}': {}", data_dir.display(), e), true);
+            }
+        }
+        eprintln!("{} '{}'", prefix, data_dir.display());
+    }
+
+    match git::git(&["config", "core.hooksPath"]) {
+        // Set by fisherman when installing into `hooks_path`
+        Some(current) if Some(current.as_str()) == hooks_path => {
+            if !dry_run && git::git(&["config", "--unset", "core.hooksPath"]).is_none() {
+                log_error("Could not unset core.hooksPath", true);
+            }
+            let prefix = if dry_run { "Would unset" } else { "Unset" };
+            eprintln!("{} core.hooksPath '{}'", prefix, current);
+        }
+        Some(current) => eprintln!("core.hooksPath still points to '{}'", current),
+        None => (),
+    }
+}
+
+fn legacy_path(dir: &Path, filename: &str) -> PathBuf {
+    dir.join(format!("{}{}", filename, LEGACY_SUFFIX))
+}
+
+fn fingerprint_of(path: &Path) -> Option<String> {
+    let content = fs::read_to_string(path).ok()?;
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix(FINGERPRINT_PREFIX))
+        .map(String::from)
+}
+
+fn is_generated(path: &Path) -> bool {
+    match fs::read_to_string(path) {
+        Ok(content) => content.lines().any(|line| line == HOOK_MARKER),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{chained_hooks, hook_script, quote, HOOK_MARKER};
+    use std::{env, fs};
+
+    #[test]
+    fn test_hook_script_leaves_legacy_to_fisherman() {
+        let args = vec![String::from("run"), String::from("pre-push")];
+        let script = hook_script(&args, "0");
+        assert!(script.ends_with("exec \"$fisherman\" 'run' 'pre-push' \"$@\"\n"));
+        assert!(!script.contains("legacy"));
+    }
+
+    #[test]
+    fn test_chained_hooks_keep_their_name_once_moved() {
+        let dir = env::temp_dir().join(format!("fisherman-test-chained-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("pre-commit"), "#!/bin/sh\n").unwrap();
+        fs::write(dir.join("pre-push.fisherman-legacy"), "#!/bin/sh\n").unwrap();
+        fs::write(dir.join("commit-msg"), format!("#!/bin/sh\n{}\n", HOOK_MARKER)).unwrap();
+        fs::write(dir.join("post-merge"), "#!/bin/sh\n").unwrap();
+        let hooks = [String::from("pre-commit"), String::from("commit-msg")];
+        let names: Vec<String> = chained_hooks(&dir, &hooks).into_iter().map(|(name, _)| name).collect();
+        fs::rename(dir.join("pre-commit"), dir.join("pre-commit.fisherman-legacy")).unwrap();
+        let moved: Vec<String> = chained_hooks(&dir, &hooks).into_iter().map(|(name, _)| name).collect();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(vec!["pre-commit", "pre-push"], names);
+        assert_eq!(names, moved);
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!("'it'\\''s $HOME'", quote("it's $HOME"));
+    }
+}
@@ -0,0 +1,509 @@
+use colored::Colorize;
+use core::fmt;
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Component, Path, PathBuf},
+    process::exit,
+};
+use toml::{Table, Value};
+use toml_edit::{DocumentMut, Item};
+
+use crate::{
+    config::Config,
+    diagnostics, git,
+    logger::log_error,
+    migrations::{self, CONFIG_VERSION},
+    validators::toml_error,
+};
+
+/**
+ * Untracked personal configuration, merged over the repository one
+ */
+pub const LOCAL_FILE: &str = ".fisherman.local.toml";
+
+/**
+ * The configuration merged from every layer, with the file each value comes
+ * from keyed by its dotted path
+ */
+pub struct Resolved {
+    pub config: Config,
+    pub table: Table,
+    pub origins: BTreeMap<String, String>,
+}
+
+/**
+ * `$XDG_CONFIG_HOME/fisherman/config.toml`, `$XDG_CONFIG_HOME` defaulting
+ * to `~/.config`
+ */
+pub fn user_file() -> Option<PathBuf> {
+    let config_home = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_home.join("fisherman").join("config.toml"))
+}
+
+/**
+ * Personal layers present on this machine, least specific first
+ */
+fn overlays() -> Vec<String> {
+    user_file()
+        .map(|path| path.to_string_lossy().to_string())
+        .into_iter()
+        .chain([String::from(LOCAL_FILE)])
+        .filter(|path| Path::new(path).is_file())
+        .collect()
+}
+
+/**
+ * A base configuration named by `extends`: a file, relative to the one
+ * extending it, or a file in another local git repository at a pinned ref
+ */
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum Extend {
+    Path(String),
+    Git {
+        /// Path of the git repository, relative to the extending file
+        repo: String,
+        /// Tag or commit to read the file at
+        #[serde(rename = "ref")]
+        reference: String,
+        /// Path of the file in the repository
+        path: String,
+    },
+}
+
+/**
+ * Where a configuration layer is read from
+ */
+enum Source {
+    File(PathBuf),
+    Git {
+        repo: PathBuf,
+        reference: String,
+        path: PathBuf,
+    },
+}
+
+impl Source {
+    fn read(&self) -> Option<String> {
+        match self {
+            Source::File(path) => fs::read_to_string(path).ok(),
+            Source::Git { repo, reference, path } => git::git(&[
+                "-C",
+                &repo.to_string_lossy(),
+                "show",
+                &format!("{}:{}", reference, path.to_string_lossy()),
+            ]),
+        }
+    }
+
+    /**
+     * Source of `entry`, relative paths are resolved from this source
+     */
+    fn join(&self, entry: &Extend) -> Source {
+        // Directory relative paths start from, on disk
+        let directory = match self {
+            Source::File(path) => path.parent().map(Path::to_path_buf).unwrap_or_default(),
+            Source::Git { repo, path, .. } => repo.join(path.parent().unwrap_or(Path::new(""))),
+        };
+        match (self, entry) {
+            (_, Extend::Path(path)) if Path::new(path).is_absolute() => Source::File(PathBuf::from(path)),
+            (Source::File(_), Extend::Path(path)) => Source::File(directory.join(path)),
+            // A relative path in a repository stays at the same ref
+            (Source::Git { repo, reference, path: from }, Extend::Path(path)) => Source::Git {
+                repo: repo.clone(),
+                reference: reference.clone(),
+                path: normalize(&from.parent().unwrap_or(Path::new("")).join(path)),
+            },
+            (_, Extend::Git { repo, reference, path }) => Source::Git {
+                repo: directory.join(repo),
+                reference: reference.clone(),
+                path: normalize(Path::new(path)),
+            },
+        }
+    }
+
+    /**
+     * Identity of the source for cycle detection
+     */
+    fn key(&self) -> String {
+        match self {
+            Source::File(path) => fs::canonicalize(path).unwrap_or(path.clone()).to_string_lossy().to_string(),
+            Source::Git { repo, reference, path } => {
+                let repo_path = repo.to_string_lossy().to_string();
+                let commit = git::git(&["-C", &repo_path, "rev-parse", &format!("{}^{{commit}}", reference)]);
+                format!(
+                    "{}@{}:{}",
+                    fs::canonicalize(repo).unwrap_or(repo.clone()).to_string_lossy(),
+                    commit.as_deref().unwrap_or(reference),
+                    path.to_string_lossy()
+                )
+            }
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::File(path) => write!(f, "{}", path.to_string_lossy()),
+            Source::Git { repo, reference, path } => {
+                write!(f, "{}@{}:{}", repo.to_string_lossy(), reference, path.to_string_lossy())
+            }
+        }
+    }
+}
+
+/**
+ * `path` without `.` and `..` components, for paths inside a git tree
+ */
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::Normal(part) => normalized.push(part),
+            _ => (),
+        }
+    }
+    normalized
+}
+
+/**
+ * Resolve the configuration at `path` with its bases merged below it and
+ * the personal layers merged over it, None if there is no configuration.
+ * Problems are reported and fisherman exits.
+ */
+pub fn resolve(path: &str) -> Option<Resolved> {
+    let content = fs::read_to_string(path).ok()?;
+    let (repository, from) = parse_layer(path, &content, true);
+    let overlays = overlays();
+    let mut origins: BTreeMap<String, String> = BTreeMap::new();
+
+    // A single file keeps the precise location of layout errors
+    if overlays.is_empty() && !repository.contains_key("extends") {
+        let config = match diagnostics::parse(&content) {
+            Ok(config) => config,
+            Err(error) => {
+                diagnostics::report(path, &error);
+                exit(1);
+            }
+        };
+        let table = match Value::try_from(&config) {
+            Ok(Value::Table(table)) => table,
+            _ => unreachable!(),
+        };
+        for (key, value) in &table {
+            record(value, key, path, &mut origins);
+        }
+        return Some(Resolved { config, table, origins });
+    }
+
+    let mut layers: Vec<(String, Table)> = Vec::new();
+    flatten(&Source::File(PathBuf::from(path)), repository, &mut Vec::new(), &mut layers);
+    // The repository configuration and its bases come first
+    let shared_layers = layers.len();
+    for overlay in &overlays {
+        let content = match fs::read_to_string(overlay) {
+            Ok(content) => content,
+            Err(e) => {
+                log_error(&format!("{}: {}", overlay, e), true);
+                unreachable!();
+            }
+        };
+        // Alone a personal layer may lack required keys, but an unknown key
+        // is located in its own file
+        if let Err(e) = toml::from_str::<Config>(&content) {
+            if e.message().starts_with("unknown field") {
+                diagnostics::report(overlay, &toml_error(&content, Some(e)).unwrap());
+                exit(1);
+            }
+        }
+        let (layer, _) = parse_layer(overlay, &content, false);
+        flatten(&Source::File(PathBuf::from(overlay)), layer, &mut Vec::new(), &mut layers);
+    }
+
+    let sources: Vec<String> = layers.iter().map(|(origin, _)| origin.clone()).collect();
+    let mut table = Table::new();
+    let mut shared = Table::new();
+    for (index, (origin, mut layer)) in layers.into_iter().enumerate() {
+        if index == shared_layers {
+            shared = table.clone();
+        }
+        // Only the repository configuration decides its version and bases
+        if origin != path {
+            layer.remove("version");
+            layer.remove("extends");
+        }
+        merge(&mut table, layer, "", &origin, &mut origins);
+    }
+    if overlays.is_empty() {
+        shared = table.clone();
+    }
+    table.insert(String::from("version"), Value::Integer(i64::from(from)));
+    let shared_hooks = Value::Table(shared).try_into::<Config>().ok().map(|shared| shared.hook_names());
+    match Value::Table(table.clone()).try_into::<Config>() {
+        Ok(config) => Some(Resolved {
+            config: Config { shared_hooks, ..config },
+            table,
+            origins,
+        }),
+        Err(e) => {
+            let culprit = culprit(e.message(), &origins, &sources).unwrap_or(String::from(path));
+            diagnostics::report_message(&culprit, e.message());
+            exit(1);
+        }
+    }
+}
+
+/**
+ * Parse a layer as a table, with the version it had. Configurations are
+ * migrated to the current layout, personal layers are always current.
+ */
+fn parse_layer(origin: &str, content: &str, migrate: bool) -> (Table, u32) {
+    let (content, from) = match migrate {
+        true => match migrations::upgrade(content, false) {
+            Ok((upgraded, from)) => {
+                migrations::warn_placeholders(origin, content, from);
+                (upgraded, from)
+            }
+            Err(error) => {
+                diagnostics::report(origin, &error);
+                exit(1);
+            }
+        },
+        false => (content.to_string(), CONFIG_VERSION),
+    };
+    match toml::from_str(&content) {
+        Ok(table) => (table, from),
+        Err(e) => {
+            diagnostics::report(origin, &toml_error(&content, Some(e)).unwrap());
+            exit(1);
+        }
+    }
+}
+
+/**
+ * Push the bases of `table` then `table` itself to `layers`, from the least
+ * to the most specific. `stack` holds the sources being extended.
+ */
+fn flatten(source: &Source, table: Table, stack: &mut Vec<String>, layers: &mut Vec<(String, Table)>) {
+    let key = source.key();
+    if stack.contains(&key) {
+        log_error(&format!("`extends` cycle: {} -> {}", stack.join(" -> "), key), true);
+    }
+    stack.push(key);
+    let extends: Vec<Extend> = match table.get("extends").cloned().map(Value::try_into) {
+        Some(Ok(extends)) => extends,
+        Some(Err(e)) => {
+            diagnostics::report_message(&source.to_string(), &format!("invalid `extends`: {}", e.message()));
+            exit(1);
+        }
+        None => Vec::new(),
+    };
+    for entry in &extends {
+        let base = source.join(entry);
+        let Some(content) = base.read() else {
+            log_error(&format!("{}: cannot read the base configuration {}", source, base), true);
+            unreachable!();
+        };
+        let (base_table, _) = parse_layer(&base.to_string(), &content, true);
+        flatten(&base, base_table, stack, layers);
+    }
+    stack.pop();
+    layers.push((source.to_string(), table));
+}
+
+/**
+ * Merge `layer` over `base`: tables are merged key by key, any other value
+ * replaces the one below, and `false` in place of a table removes it
+ */
+fn merge(base: &mut Table, layer: Table, prefix: &str, origin: &str, origins: &mut BTreeMap<String, String>) {
+    for (key, value) in layer {
+        let path = join(prefix, &key);
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(below)), Value::Table(above)) => merge(below, above, &path, origin, origins),
+            (Some(Value::Table(_)), Value::Boolean(false)) => {
+                base.remove(&key);
+                forget(&path, origins);
+            }
+            (_, value) => {
+                forget(&path, origins);
+                record(&value, &path, origin, origins);
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+fn record(value: &Value, path: &str, origin: &str, origins: &mut BTreeMap<String, String>) {
+    match value {
+        Value::Table(table) => {
+            for (key, value) in table {
+                record(value, &join(path, key), origin, origins);
+            }
+        }
+        _ => {
+            origins.insert(path.to_string(), origin.to_string());
+        }
+    }
+}
+
+fn forget(path: &str, origins: &mut BTreeMap<String, String>) {
+    let nested = format!("{}.", path);
+    origins.retain(|key, _| key != path && !key.starts_with(&nested));
+}
+
+fn join(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+
+/**
+ * File of the value an error is about, when the message names a key. The
+ * most specific layer setting it is the likeliest to be wrong.
+ */
+fn culprit(message: &str, origins: &BTreeMap<String, String>, layers: &[String]) -> Option<String> {
+    let key = Regex::new(r"`([^`]+)`").unwrap().captures(message)?[1].to_string();
+    let setting: Vec<&String> = origins
+        .iter()
+        .filter(|(path, _)| path.split('.').any(|part| part == key))
+        .map(|(_, origin)| origin)
+        .collect();
+    layers.iter().rev().find(|layer| setting.contains(layer)).cloned()
+}
+
+/**
+ * Keep the personal layer out of git with `.git/info/exclude`
+ */
+pub fn exclude_local() {
+    let Some(exclude) = git::git(&["rev-parse", "--git-path", "info/exclude"]).map(PathBuf::from) else {
+        return;
+    };
+    let content = fs::read_to_string(&exclude).unwrap_or_default();
+    let pattern = format!("/{}", LOCAL_FILE);
+    if content.lines().any(|line| line == pattern || line == LOCAL_FILE) {
+        return;
+    }
+    let separator = if content.is_empty() || content.ends_with('\n') { "" } else { "\n" };
+    let written = exclude
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&exclude, format!("{}{}{}\n", content, separator, pattern)));
+    if let Err(e) = written {
+        eprintln!("{} cannot exclude {} from git: {}", "[Warning]:".yellow(), LOCAL_FILE, e);
+    }
+}
+
+/**
+ * File a value comes from: its own, the one of the array or table holding
+ * it, or the one of its first value for a table
+ */
+fn origin_of<'a>(path: &str, origins: &'a BTreeMap<String, String>) -> Option<&'a String> {
+    let mut ancestor = path;
+    loop {
+        if let Some(origin) = origins.get(ancestor) {
+            return Some(origin);
+        }
+        match ancestor.rfind('.') {
+            Some(index) => ancestor = &ancestor[..index],
+            None => break,
+        }
+    }
+    let nested = format!("{}.", path);
+    origins.iter().find(|(key, _)| key.starts_with(&nested)).map(|(_, origin)| origin)
+}
+
+fn annotate(table: &mut toml_edit::Table, prefix: &str, origins: &BTreeMap<String, String>) {
+    for (key, item) in table.iter_mut() {
+        let path = join(prefix, key.get());
+        match item {
+            Item::Table(table) => annotate(table, &path, origins),
+            Item::ArrayOfTables(array) => {
+                for table in array.iter_mut() {
+                    annotate(table, &path, origins);
+                }
+            }
+            Item::Value(value) => {
+                if let Some(origin) = origin_of(&path, origins) {
+                    value.decor_mut().set_suffix(format!(" # {}", origin));
+                }
+            }
+            Item::None => (),
+        }
+    }
+}
+
+/**
+ * `fisherman config show`: print the configuration fisherman runs with,
+ * with `resolved` each value is followed by the file it comes from
+ */
+pub fn show(path: &str, resolved: bool) {
+    let Some(resolution) = resolve(path) else {
+        log_error("no configutation found", true);
+        return;
+    };
+    let mut document: DocumentMut = match toml::to_string(&resolution.table).map(|toml| toml.parse()) {
+        Ok(Ok(document)) => document,
+        _ => unreachable!(),
+    };
+    if resolved {
+        annotate(document.as_table_mut(), "", &resolution.origins);
+    }
+    print!("{}", document);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge, record, Extend, Source};
+    use std::{collections::BTreeMap, path::PathBuf};
+    use toml::Table;
+
+    #[test]
+    fn test_merge() {
+        let mut base: Table = toml::from_str(
+            "[pre_commit.lint]\nfile_ext = \"rs\"\nsingle_file = true\n[pre_commit.test.command]\nname = \"cargo\"\n",
+        )
+        .unwrap();
+        let mut origins = BTreeMap::new();
+        for (key, value) in &base {
+            record(value, key, "repo", &mut origins);
+        }
+        let layer: Table = toml::from_str("[pre_commit]\ntest = false\n[pre_commit.lint]\nsingle_file = false\n").unwrap();
+        merge(&mut base, layer, "", "local", &mut origins);
+
+        let expected: Table = toml::from_str("[pre_commit.lint]\nfile_ext = \"rs\"\nsingle_file = false\n").unwrap();
+        assert_eq!(expected, base);
+        assert_eq!(Some(&String::from("repo")), origins.get("pre_commit.lint.file_ext"));
+        assert_eq!(Some(&String::from("local")), origins.get("pre_commit.lint.single_file"));
+        assert_eq!(None, origins.get("pre_commit.test.command.name"));
+    }
+
+    #[test]
+    fn test_extends_sources() {
+        let file = Source::File(PathBuf::from("config/.fisherman.toml"));
+        let git = file.join(&Extend::Git {
+            repo: String::from("../org"),
+            reference: String::from("v1"),
+            path: String::from("./shared/rust.toml"),
+        });
+        assert_eq!("config/../org@v1:shared/rust.toml", git.to_string());
+        assert_eq!(
+            "config/../org@v1:common.toml",
+            git.join(&Extend::Path(String::from("../common.toml"))).to_string()
+        );
+        assert_eq!("/etc/base.toml", git.join(&Extend::Path(String::from("/etc/base.toml"))).to_string());
+        assert_eq!("config/base.toml", file.join(&Extend::Path(String::from("base.toml"))).to_string());
+    }
+}
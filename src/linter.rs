@@ -3,16 +3,33 @@ use core::fmt;
 use inquire::{Confirm, Text};
 use regex::bytes::Regex;
 use spinners::{Spinner, Spinners};
-use std::process::{exit, Command};
+use std::{
+    path::Path,
+    process::{exit, Command},
+};
 
-use crate::config::{fmt_single_parameter, Cmd};
+use crate::{
+    conditions::When,
+    config::{fmt_single_parameter, Cmd},
+    git,
+    placeholders::{self, Context},
+};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Lint {
+    /// Linter command
     pub command: Cmd,
+    /// Regex of the files to lint
     pub file_ext: String,
+    /// Lint each staged file instead of the whole project
     pub single_file: bool,
+    /// Conditions the check runs under
+    pub when: Option<When>,
+    /// Checks and tasks that must succeed first
+    pub depends_on: Option<Vec<String>>,
 }
 
 impl fmt::Display for Lint {
@@ -42,38 +59,45 @@ impl Lint {
             command,
             file_ext,
             single_file,
+            when: None,
+            depends_on: None,
         }
     }
 
     pub fn run(&self) {
-        if !self.command.check() {
-            eprintln!(
-                "Fisherman Error: Linter `{}` not found.",
-                &self.command.name
-            );
-            exit(1);
-        }
         let mut spinner = Spinner::new(Spinners::Dots, "Fisherman: Linting in progress".into());
-        let (success, sout, serr) = if self.single_file {
-            self.lint_file()
-        } else {
-            self.lint_project()
-        };
+        let (success, report) = self.execute();
 
         if success {
             spinner.stop_and_persist(&format!("{}", "".green()), "Fisherman: Linting OK".into());
         } else {
             spinner.stop_and_persist(&format!("{}", "".red()), "Fisherman: Linting Failed".into());
-            eprintln!("{}", sout);
-            eprintln!("{}", serr);
+        }
+        if !report.is_empty() {
+            eprintln!("{}", report);
+        }
+        if !success {
             exit(1);
         }
     }
 
+    /**
+     * Lint the staged files or the project, return whether the linter
+     * succeeded and its output
+     */
+    pub fn execute(&self) -> (bool, String) {
+        if !self.command.check() {
+            return (false, format!("Fisherman Error: Linter `{}` not found.", &self.command.name));
+        }
+        let (success, sout, serr) = if self.single_file {
+            self.lint_file()
+        } else {
+            self.lint_project()
+        };
+        (success, format!("{}\n{}", sout.trim_end(), serr.trim_end()).trim().to_string())
+    }
+
     fn lint_file(&self) -> (bool, String, String) {
-        let mut res = true;
-        let mut sout = String::from("");
-        let mut serr = String::from("");
         let staged_files = Command::new("git")
             .arg("diff")
             .arg("--cached")
@@ -81,48 +105,116 @@ impl Lint {
             .output()
             .expect("git cannot be executed");
         let staged_files = String::from_utf8(staged_files.stdout).unwrap();
-        let staged_files = staged_files.split('\n');
-        let re = Regex::new(&self.file_ext).unwrap();
-        for file in staged_files {
-            if re.is_match(file.as_bytes()) {
-                eprintln!("{}", file);
-                let binding = Vec::new();
-                let args: &Vec<String> = match &self.command.args {
-                    Some(args) => &args.0,
-                    None => &binding,
-                };
+        let staged_files: Vec<String> = staged_files.split('\n').map(String::from).collect();
+        self.lint_files(&staged_files, None, Context::hook("pre-commit"))
+    }
 
-                let output = Command::new(&self.command.name)
-                    .args(args)
-                    .arg(file)
-                    .output()
-                    .expect("");
-                if !output.status.success() {
-                    res = false;
-                    sout = String::from_utf8(output.stdout).expect("Not UTF-8");
-                    serr = String::from_utf8(output.stderr).expect("Not UTF-8");
+    /**
+     * Lint the `files` matching `file_ext`, relative to `dir` if given
+     */
+    pub fn lint_files(&self, files: &[String], dir: Option<&Path>, context: Context) -> (bool, String, String) {
+        if !self.command.check() {
+            return (false, String::new(), format!("Linter `{}` not found", self.command.name));
+        }
+        let mut res = true;
+        let mut sout = String::from("");
+        let mut serr = String::from("");
+        // Files linted and files passing on a retry, reported along the
+        // output since the linter may run on a job thread
+        let mut linted = String::from("");
+        let mut flaky = String::from("");
+        let files = match self.matching(files) {
+            Ok(files) => files,
+            Err(message) => return (false, String::new(), message),
+        };
+        // The file is appended unless the arguments place it themselves, and
+        // `{files}` without `{file}` takes every file in a single run
+        let args = self.command.args.as_ref().map(|args| args.0.as_slice()).unwrap_or_default();
+        let placed = placeholders::uses_files(args);
+        let once = placeholders::uses(args, "files") && !placeholders::uses(args, "file");
+        let targets: Vec<Option<&String>> = match once {
+            true if files.is_empty() => Vec::new(),
+            true => vec![None],
+            false => files.iter().map(Some).collect(),
+        };
+        for target in targets {
+            let file = target.cloned().unwrap_or_else(|| files.join(" "));
+            linted.push_str(&format!("{}\n", file));
+            let context = Context {
+                files: Some(files.clone()),
+                file: target.cloned(),
+                ..context.clone()
+            };
+            let args = match self.command.expand_args(&context) {
+                Ok(args) => args,
+                Err(message) => return (false, String::new(), message),
+            };
+            let output = self
+                .command
+                .retrying(|| {
+                    let mut command = self.command.command(dir);
+                    command.args(&args);
+                    if !placed {
+                        command.arg(&file);
+                    }
+                    command.output()
+                });
+            let output = match output {
+                Ok((output, retry)) if output.status.success() && retry > 0 => {
+                    flaky.push_str(&format!("{} {} {}\n", "[Warning]:".yellow(), file, self.command.fmt_retry(retry)));
+                    output
                 }
+                Ok((output, _)) => output,
+                Err(e) => return (false, String::new(), e.to_string()),
+            };
+            if !output.status.success() {
+                res = false;
+                sout = String::from_utf8_lossy(&output.stdout).to_string();
+                serr = String::from_utf8_lossy(&output.stderr).to_string();
             }
         }
-        (res, sout, serr)
+        (res, linted + &flaky + &sout, serr)
+    }
+
+    /**
+     * The `files` matching `file_ext`, or why `file_ext` is not a regex
+     */
+    fn matching(&self, files: &[String]) -> Result<Vec<String>, String> {
+        let re = Regex::new(&self.file_ext)
+            .map_err(|e| format!("pre_commit.lint.file_ext: invalid regex `{}`: {}", self.file_ext, e))?;
+        Ok(files.iter().filter(|file| re.is_match(file.as_bytes())).cloned().collect())
     }
 
     fn lint_project(&self) -> (bool, String, String) {
-        let binding = Vec::new();
         let mut sout = String::from("");
         let mut serr = String::from("");
-        let args: &Vec<String> = match &self.command.args {
-            Some(args) => &args.0,
-            None => &binding,
-        };
+        // `{files}` are the staged files the linter applies to
+        let mut context = Context::hook("pre-commit");
+        if self.command.args.as_ref().is_some_and(|args| placeholders::uses_files(&args.0)) {
+            match self.matching(&git::staged_files()) {
+                Ok(files) => context.files = Some(files),
+                Err(message) => return (false, sout, message),
+            }
+        }
 
-        let output = Command::new(&self.command.name)
-            .args(args)
-            .output()
-            .expect("");
+        let args = match self.command.expand_args(&context) {
+            Ok(args) => args,
+            Err(message) => return (false, sout, message),
+        };
+        let output = self
+            .command
+            .retrying(|| self.command.command(None).args(&args).output());
+        let output = match output {
+            Ok((output, retry)) if output.status.success() && retry > 0 => {
+                sout = format!("{} {}", "[Warning]:".yellow(), self.command.fmt_retry(retry));
+                output
+            }
+            Ok((output, _)) => output,
+            Err(e) => return (false, sout, e.to_string()),
+        };
         if !output.status.success() {
-            sout = String::from_utf8(output.stdout).expect("Not UTF-8");
-            serr = String::from_utf8(output.stderr).expect("Not UTF-8");
+            sout = String::from_utf8_lossy(&output.stdout).to_string();
+            serr = String::from_utf8_lossy(&output.stderr).to_string();
         }
         (output.status.success(), sout, serr)
     }
@@ -1,15 +1,32 @@
+mod branch;
+mod bypass;
 mod commit_message;
+mod conditions;
 mod config;
+mod diagnostics;
 mod linter;
+mod layers;
 mod logger;
+mod migrations;
+mod placeholders;
 mod pre_commit;
+mod secrets;
+mod tasks;
 mod tester;
 mod flags;
+mod git;
+mod hooks;
+mod hygiene;
+mod installer;
+mod server;
+mod sync;
+mod trust;
+mod validators;
 use config::Config;
 use std::str::FromStr;
 use logger::log_error;
 use std::env;
-use std::fs::{self, File};
+use std::fs::File;
 use std::io::Write;
 use std::path::Path;
 use flags::Flags;
@@ -20,13 +37,40 @@ use crate::pre_commit::PreCommit;
 const CONFIG_FILE: &str = ".fisherman.toml";
 
 fn main() {
-    // Get Toml config
-    let config: Option<Config> = match fs::read_to_string(CONFIG_FILE) {
-        Ok(result) => Some(toml::from_str(&result).unwrap()),
-        Err(_) => None,
-    };
+    // Work from the repository root so subdirectories find the config
+    if let Some(root) = git::toplevel() {
+        let _ = env::set_current_dir(root);
+    }
+
     let args: Vec<String> = env::args().collect();
 
+    // Validation reports every problem instead of exiting on the first one,
+    // the schema does not depend on the configuration at all
+    if let Some(Ok(Flags::Config)) = args.get(1).map(|arg| Flags::from_str(arg)) {
+        match args.get(2).map(String::as_str) {
+            Some("validate") => diagnostics::validate(CONFIG_FILE),
+            Some("schema") => diagnostics::schema(),
+            Some("migrate") => migrations::migrate(CONFIG_FILE),
+            Some("show") => layers::show(CONFIG_FILE, args[3..].iter().any(|arg| arg == "--resolved")),
+            _ => log_error("usage: fisherman config validate|schema|migrate|show [--resolved]", true),
+        }
+        return;
+    }
+
+    // Get Toml config
+    let config: Option<Config> = diagnostics::load(CONFIG_FILE);
+
+    // Uninstalling must work even when the configuration is gone
+    if let Some(Ok(Flags::Uninstall)) = args.get(1).map(|arg| Flags::from_str(arg)) {
+        let options = &args[2..];
+        installer::uninstall(
+            options.iter().any(|o| o == "--purge"),
+            options.iter().any(|o| o == "--dry-run"),
+            config.as_ref().and_then(|config| config.hooks_path.as_deref()),
+        );
+        return;
+    }
+
     if args.len() == 1 {
      match config {
             Some(c) => {
@@ -48,7 +92,7 @@ fn main() {
 
 fn init() {
     eprintln!("Welcome to fisherman, your git hooks manager!");
-    if !Path::new("./.git/").exists() {
+    if git::git(&["rev-parse", "--git-dir"]).is_none() {
         log_error("git is not initialized in this directory", true);
     }
 
@@ -60,7 +104,11 @@ fn init() {
             eprintln!("{}", config);
             unsafe {
                 match file.write_all(toml.as_bytes_mut()) {
-                    Ok(_) => eprintln!("Configuration created!"),
+                    Ok(_) => {
+                        eprintln!("Configuration created!");
+                        // The commands were just typed in by the user
+                        trust::trust(&config, Path::new(CONFIG_FILE), false);
+                    }
                     Err(e) => {
                         log_error(&e.to_string(), true);
                     }
@@ -81,39 +129,69 @@ fn execute(args: Vec<String>, config: Config) {
     let pre_commit = config.pre_commit.clone().unwrap_or(PreCommit {
         lint: None,
         test: None,
+        hygiene: None,
+        secrets: None,
+        validators: None,
+        tasks: None,
+        jobs: None,
+        fail_fast: None,
     });
-    env::set_current_dir(&config.root_directory).unwrap();
     let action: Flags = Flags::from_str(&args[1]).unwrap();
+    // Hooks may chain scripts of a tracked directory, an untrusted
+    // configuration does not install anything
+    if !matches!(action, Flags::Init | Flags::ApplyHooks) && trust::is_trusted(&config, Path::new(CONFIG_FILE)) {
+        config.refresh_hooks();
+    }
+    if matches!(
+        action,
+        Flags::Lint | Flags::Test | Flags::PreCommit | Flags::CommitMessage | Flags::Run | Flags::ApplyHooks
+    ) {
+        trust::check(&config, Path::new(CONFIG_FILE));
+    }
+    if let Some(root_directory) = &config.root_directory {
+        if let Err(e) = env::set_current_dir(root_directory) {
+            log_error(&format!("cannot enter '{}': {}", root_directory, e), true);
+        }
+    }
     match action {
         Flags::Init => init(),
         Flags::Lint => {
-            if pre_commit.lint.is_some() {
-                pre_commit.lint.unwrap().run();
+            if let Some(lint) = pre_commit.lint {
+                lint.run();
             } else {
                 log_error("lint config not found", true);
             }
         }
         Flags::Test => {
-            if pre_commit.test.is_some() {
-                pre_commit.test.unwrap().run();
+            if let Some(test) = pre_commit.test {
+                test.run();
             } else {
                 log_error("test config not found", true);
             }
         }
         Flags::PreCommit => {
-            pre_commit.run();
+            config.run_hook("pre-commit", &[]);
         }
         Flags::CommitMessage => {
             let commit_message_path = &args[2.. args.len()];
-            if config.commit_message.is_some() {
-                config.commit_message.unwrap().run(Some(&commit_message_path.join(" ")), None);
+            if let Some(commit_message) = config.commit_message {
+                println!("{}", commit_message.run(Some(&commit_message_path.join(" ")), None));
             }
         }
+        Flags::Run => match args.get(2) {
+            Some(hook) => config.run_hook(hook, &args[3..]),
+            None => log_error("usage: fisherman run <hook> [git args...]", true),
+        },
         Flags::ShowConfig => {
             specs(&config)
         },
 
         Flags::ApplyHooks => config.apply(),
+        Flags::Trust => trust::trust(
+            &config,
+            Path::new(CONFIG_FILE),
+            !args[2..].iter().any(|arg| arg == "-y" || arg == "--yes"),
+        ),
         Flags::Help => (),
         _ => {
             eprintln!("Nothing to do");
@@ -0,0 +1,270 @@
+use colored::Colorize;
+use std::{fs, path::Path};
+use toml_edit::{DocumentMut, ImDocument, Item, Value};
+
+use crate::{git, logger::log_error, placeholders, validators::{position, Location}};
+
+/**
+ * Layout version written by this fisherman. A configuration without
+ * `version` predates versioning and is version 1.
+ */
+pub const CONFIG_VERSION: u32 = 3;
+
+/**
+ * Rewrite of a configuration from version `from` to the next one
+ */
+struct Migration {
+    from: u32,
+    description: &'static str,
+    apply: fn(&mut DocumentMut),
+    /// Also applied in memory when loading, not only by `fisherman config migrate`
+    on_load: bool,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 1,
+        description: "`root_directory` is relative to the repository root",
+        apply: relative_root_directory,
+        on_load: true,
+    },
+    Migration {
+        from: 2,
+        description: "braces and `$` of command arguments are escaped, `{...}` is a placeholder",
+        apply: escape_args,
+        // A hand-written file without `version` means its placeholders
+        on_load: false,
+    },
+];
+
+/**
+ * Version 1 stored the absolute path of the author's checkout. A path inside
+ * this repository becomes relative, any other path was the root of someone
+ * else's checkout, which is now discovered at runtime.
+ */
+fn relative_root_directory(document: &mut DocumentMut) {
+    let Some(root_directory) = document.get("root_directory").and_then(|item| item.as_str()) else {
+        return;
+    };
+    let path = Path::new(root_directory);
+    if path.is_relative() {
+        return;
+    }
+    let relative = git::toplevel()
+        .and_then(|toplevel| path.strip_prefix(toplevel).ok().map(|p| p.to_string_lossy().to_string()));
+    match relative {
+        Some(relative) if !relative.is_empty() => replace(document, "root_directory", Value::from(relative)),
+        _ => {
+            document.remove("root_directory");
+        }
+    }
+}
+
+/**
+ * Arguments were passed literally before placeholders, escape them so that
+ * they still are
+ */
+fn escape_args(document: &mut DocumentMut) {
+    for (key, item) in document.as_table_mut().iter_mut() {
+        escape_item(key.get(), item);
+    }
+}
+
+fn escape_item(key: &str, item: &mut Item) {
+    match item {
+        Item::Table(table) => {
+            for (key, item) in table.iter_mut() {
+                escape_item(key.get(), item);
+            }
+        }
+        Item::ArrayOfTables(tables) => {
+            for table in tables.iter_mut() {
+                for (key, item) in table.iter_mut() {
+                    escape_item(key.get(), item);
+                }
+            }
+        }
+        Item::Value(value) => escape_value(key, value),
+        Item::None => (),
+    }
+}
+
+fn escape_value(key: &str, value: &mut Value) {
+    match value {
+        Value::InlineTable(table) => {
+            for (key, value) in table.iter_mut() {
+                escape_value(key.get(), value);
+            }
+        }
+        Value::Array(args) if key == "args" => {
+            for arg in args.iter_mut() {
+                let Some(escaped) = arg
+                    .as_str()
+                    .map(placeholders::escape)
+                    .filter(|escaped| Some(escaped.as_str()) != arg.as_str())
+                else {
+                    continue;
+                };
+                let mut escaped = Value::from(escaped);
+                *escaped.decor_mut() = arg.decor().clone();
+                *arg = escaped;
+            }
+        }
+        Value::Array(values) => {
+            for value in values.iter_mut() {
+                escape_value("", value);
+            }
+        }
+        _ => (),
+    }
+}
+
+/**
+ * Version of the configuration `content`, 1 when it has none
+ */
+fn version(content: &str) -> Result<u32, Location> {
+    // Only the immutable document keeps the spans
+    let Ok(document) = ImDocument::parse(content) else {
+        return Ok(CONFIG_VERSION);
+    };
+    let Some(item) = document.get("version") else {
+        return Ok(1);
+    };
+    let (line, column) = position(content, item.span().map(|span| span.start).unwrap_or(0));
+    let error = |message: String| Location { line, column, message };
+    match item.as_integer() {
+        Some(version) if version >= 1 && version <= i64::from(CONFIG_VERSION) => Ok(version as u32),
+        Some(version) if version > i64::from(CONFIG_VERSION) => Err(error(format!(
+            "configuration version {} is newer than the supported version {}, update fisherman",
+            version, CONFIG_VERSION
+        ))),
+        _ => Err(error(String::from("`version` must be a positive integer"))),
+    }
+}
+
+/**
+ * Bring `content` to the current layout, returning it with the version it
+ * had. Comments and ordering are preserved. Content that is not valid TOML
+ * is returned untouched for the parser to report. Only an `explicit`
+ * migration rewrites what a file may have meant in the current layout.
+ */
+pub fn upgrade(content: &str, explicit: bool) -> Result<(String, u32), Location> {
+    let from = version(content)?;
+    let Ok(mut document) = content.parse::<DocumentMut>() else {
+        return Ok((content.to_string(), CONFIG_VERSION));
+    };
+    if from == CONFIG_VERSION {
+        return Ok((content.to_string(), from));
+    }
+    for migration in MIGRATIONS.iter().filter(|m| m.from >= from && (explicit || m.on_load)) {
+        (migration.apply)(&mut document);
+    }
+    replace(&mut document, "version", Value::from(i64::from(CONFIG_VERSION)));
+    Ok((document.to_string(), from))
+}
+
+/**
+ * Replace the value of an existing top level `key`, keeping its comments
+ */
+fn replace(document: &mut DocumentMut, key: &str, mut value: Value) {
+    if let Some(old) = document.get_mut(key).and_then(|item| item.as_value_mut()) {
+        *value.decor_mut() = old.decor().clone();
+        *old = value;
+    }
+}
+
+/**
+ * `fisherman config migrate`: rewrite the configuration at `path` to the
+ * current layout
+ */
+pub fn migrate(path: &str) {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => {
+            log_error(&format!("{}: {}", path, e), true);
+            unreachable!();
+        }
+    };
+    let (migrated, from) = match upgrade(&content, true) {
+        Ok(upgraded) => upgraded,
+        Err(error) => {
+            log_error(&format!("{}:{}:{}: {}", path, error.line, error.column, error.message), true);
+            unreachable!();
+        }
+    };
+    if from == CONFIG_VERSION {
+        eprintln!("{} is already at version {}", path, CONFIG_VERSION);
+        return;
+    }
+    // An unversioned file gets the version as its first key
+    let migrated = if migrated.parse::<DocumentMut>().is_ok_and(|d| d.contains_key("version")) {
+        migrated
+    } else {
+        format!("version = {}\n\n{}", CONFIG_VERSION, migrated)
+    };
+    if let Err(e) = fs::write(path, migrated) {
+        log_error(&format!("{}: {}", path, e), true);
+    }
+    eprintln!("{} migrated from version {} to {}:", path, from, CONFIG_VERSION);
+    for migration in MIGRATIONS.iter().filter(|m| m.from >= from) {
+        eprintln!(" - {}", migration.description);
+    }
+}
+
+/**
+ * Warn that the configuration at `path` uses an older layout
+ */
+pub fn warn_outdated(path: &str, from: u32) {
+    if from < CONFIG_VERSION {
+        eprintln!(
+            "{} {} uses configuration version {}, run `fisherman config migrate`",
+            "[Warning]:".yellow(),
+            path,
+            from
+        );
+    }
+}
+
+/**
+ * Warn that the arguments of the older configuration `content` at `path`
+ * have braces or `$`, now read as placeholders until it is migrated
+ */
+pub fn warn_placeholders(path: &str, content: &str, from: u32) {
+    let Some(mut document) = content.parse::<DocumentMut>().ok().filter(|_| from < CONFIG_VERSION) else {
+        return;
+    };
+    let before = document.to_string();
+    escape_args(&mut document);
+    if document.to_string() != before {
+        eprintln!(
+            "{} {} uses configuration version {}, the braces and `$` of its command arguments are read as \
+             placeholders. Run `fisherman config migrate` to keep them literal, or set `version = {}` if they are \
+             placeholders",
+            "[Warning]:".yellow(),
+            path,
+            from,
+            CONFIG_VERSION
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{upgrade, CONFIG_VERSION};
+
+    #[test]
+    fn test_upgrade() {
+        let content = "version = 1 # layout\nroot_directory = \"sub\"\n\n# tests\n[pre_commit.test.command]\nname = \"true\"\n";
+        let (migrated, from) = upgrade(content, false).unwrap();
+        assert_eq!(1, from);
+        assert_eq!(content.replacen("version = 1", &format!("version = {}", CONFIG_VERSION), 1), migrated);
+        assert_eq!((migrated.clone(), CONFIG_VERSION), upgrade(&migrated, true).unwrap());
+        assert_eq!(1, upgrade(&format!("version = {}\n", CONFIG_VERSION + 1), false).err().unwrap().line);
+        let content = "version = 2\n[hooks]\npre-push = [{ name = \"awk\", args = [\"{print $1}\", \"x\"] }]\n";
+        let (migrated, _) = upgrade(content, true).unwrap();
+        assert!(migrated.contains("args = [\"{{print $$1}}\", \"x\"]"));
+        // Loading keeps them, they may be placeholders of a file without version
+        let (loaded, _) = upgrade(content, false).unwrap();
+        assert!(loaded.contains("args = [\"{print $1}\", \"x\"]"));
+    }
+}
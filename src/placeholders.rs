@@ -0,0 +1,222 @@
+use std::{env, process::exit};
+
+use crate::{git, logger::log_error};
+
+/**
+ * Placeholders usable in the arguments of any command
+ */
+pub const PLACEHOLDERS: [&str; 8] = [
+    "files",
+    "file",
+    "root",
+    "branch",
+    "head",
+    "staged_tree",
+    "message_file",
+    "hook",
+];
+
+/**
+ * Values known where a command runs. Missing files are the staged ones,
+ * the repository values are read from git when used.
+ */
+#[derive(Default, Clone)]
+pub struct Context {
+    pub hook: Option<String>,
+    pub files: Option<Vec<String>>,
+    pub file: Option<String>,
+    pub message_file: Option<String>,
+}
+
+impl Context {
+    pub fn hook(hook: &str) -> Context {
+        Context {
+            hook: Some(hook.to_string()),
+            ..Context::default()
+        }
+    }
+
+    fn value(&self, name: &str) -> Result<Vec<String>, String> {
+        let unavailable = || {
+            format!(
+                "`{{{}}}` is not available in {}",
+                name,
+                self.hook.as_deref().unwrap_or("this command")
+            )
+        };
+        let from_git = |args: &[&str]| git::git(args).map(|value| vec![value]).ok_or_else(unavailable);
+        match name {
+            "files" => Ok(self.files.clone().unwrap_or_else(git::staged_files)),
+            "file" => self.file.clone().map(|file| vec![file]).ok_or_else(unavailable),
+            "root" => from_git(&["rev-parse", "--show-toplevel"]),
+            "branch" => from_git(&["symbolic-ref", "--short", "-q", "HEAD"]),
+            "head" => from_git(&["rev-parse", "-q", "--verify", "HEAD"]),
+            "staged_tree" => from_git(&["write-tree"]),
+            "message_file" => self.message_file.clone().map(|file| vec![file]).ok_or_else(unavailable),
+            "hook" => self.hook.clone().map(|hook| vec![hook]).ok_or_else(unavailable),
+            _ => Err(format!("unknown placeholder `{{{}}}`", name)),
+        }
+    }
+}
+
+/**
+ * A piece of an argument: literal text, a placeholder or `${VAR}`
+ */
+#[derive(Debug, PartialEq)]
+enum Part {
+    Text(String),
+    Placeholder(String),
+    Variable(String),
+}
+
+/**
+ * Split `arg` in its parts. `{{` and `}}` are literal braces, `$$` is a
+ * literal `$` as in `env` values.
+ */
+fn parse(arg: &str) -> Result<Vec<Part>, String> {
+    let mut parts: Vec<Part> = Vec::new();
+    let mut text = String::new();
+    let mut rest = arg;
+    while let Some(c) = rest.chars().next() {
+        if rest.starts_with("{{") || rest.starts_with("}}") || rest.starts_with("$$") {
+            text.push(c);
+            rest = &rest[2..];
+            continue;
+        }
+        let opening = match c {
+            '$' if rest.starts_with("${") => 2,
+            '{' => 1,
+            '}' => return Err(format!("unmatched `}}` in `{}`, write `}}}}` for a literal brace", arg)),
+            _ => 0,
+        };
+        if opening == 0 {
+            text.push(c);
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
+        let Some(end) = rest.find('}') else {
+            return Err(format!("unclosed `{{` in `{}`, write `{{{{` for a literal brace", arg));
+        };
+        if !text.is_empty() {
+            parts.push(Part::Text(std::mem::take(&mut text)));
+        }
+        let name = rest[opening..end].to_string();
+        parts.push(if opening == 2 { Part::Variable(name) } else { Part::Placeholder(name) });
+        rest = &rest[end + 1..];
+    }
+    if !text.is_empty() {
+        parts.push(Part::Text(text));
+    }
+    Ok(parts)
+}
+
+/**
+ * `arg` with its braces and `$` escaped, expanding to itself
+ */
+pub fn escape(arg: &str) -> String {
+    arg.replace('$', "$$").replace('{', "{{").replace('}', "}}")
+}
+
+/**
+ * Check the placeholders of `arg` without expanding them
+ */
+pub fn check(arg: &str) -> Result<(), String> {
+    for part in parse(arg)? {
+        if let Part::Placeholder(name) = part {
+            if !PLACEHOLDERS.contains(&name.as_str()) {
+                return Err(format!("unknown placeholder `{{{}}}` in `{}`", name, arg));
+            }
+        }
+    }
+    Ok(())
+}
+
+/**
+ * Whether `args` use the files placeholders, which replace the file
+ * otherwise appended to a linter
+ */
+pub fn uses_files(args: &[String]) -> bool {
+    uses(args, "file") || uses(args, "files")
+}
+
+/**
+ * Whether `args` use the placeholder `placeholder`
+ */
+pub fn uses(args: &[String], placeholder: &str) -> bool {
+    args.iter().any(|arg| {
+        parse(arg).is_ok_and(|parts| {
+            parts
+                .iter()
+                .any(|part| matches!(part, Part::Placeholder(name) if name == placeholder))
+        })
+    })
+}
+
+/**
+ * Expand the placeholders of `args`. An argument that is exactly `{files}`
+ * becomes one argument per file, elsewhere the files are space separated.
+ */
+pub fn expand(args: &[String], context: &Context) -> Result<Vec<String>, String> {
+    let mut expanded: Vec<String> = Vec::new();
+    for arg in args {
+        let parts = parse(arg)?;
+        if let [Part::Placeholder(name)] = parts.as_slice() {
+            expanded.extend(context.value(name)?);
+            continue;
+        }
+        let mut value = String::new();
+        for part in parts {
+            match part {
+                Part::Text(text) => value.push_str(&text),
+                Part::Placeholder(name) => value.push_str(&context.value(&name)?.join(" ")),
+                Part::Variable(name) => value.push_str(&env::var(name).unwrap_or_default()),
+            }
+        }
+        expanded.push(value);
+    }
+    Ok(expanded)
+}
+
+/**
+ * Expand `args`, reporting a wrong placeholder and exiting
+ */
+pub fn expand_or_exit(args: &[String], context: &Context) -> Vec<String> {
+    match expand(args, context) {
+        Ok(args) => args,
+        Err(message) => {
+            log_error(&message, false);
+            exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check, escape, expand, uses, Context};
+
+    #[test]
+    fn test_expand() {
+        let context = Context {
+            hook: Some(String::from("pre-commit")),
+            files: Some(vec![String::from("a.rs"), String::from("b c.rs")]),
+            ..Context::default()
+        };
+        let args: Vec<String> = ["{files}", "--hook={hook}", "{{literal}}", "${FISHERMAN_UNSET_VARIABLE}", "{files}!"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        assert_eq!(
+            vec!["a.rs", "b c.rs", "--hook=pre-commit", "{literal}", "", "a.rs b c.rs!"],
+            expand(&args, &context).unwrap()
+        );
+        assert!(expand(&[String::from("{message_file}")], &context).is_err());
+        assert!(check("{filez}").is_err());
+        assert!(check("{file").is_err());
+        assert!(check("a}").is_err());
+        let awk = String::from("{print $1} ${HOME} $$");
+        assert_eq!("{{print $$1}} $${{HOME}} $$$$", escape(&awk));
+        assert_eq!(vec![awk.clone()], expand(&[escape(&awk)], &context).unwrap());
+        assert!(uses(&args, "files") && !uses(&args, "file"));
+        assert!(!uses(&[String::from("{{files}}")], "files"));
+    }
+}
@@ -2,14 +2,49 @@ use colored::Colorize;
 use core::fmt;
 use inquire::MultiSelect;
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use crate::{linter::Lint, logger::log_error, tester::Test};
+use std::{collections::BTreeMap, process::exit, thread};
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+use crate::{
+    bypass,
+    config::fmt_single_parameter,
+    conditions::When,
+    hygiene::Hygiene,
+    linter::Lint,
+    logger::log_error,
+    secrets::Secrets,
+    tasks::{self, Job, Task},
+    tester::Test,
+    validators::Validators,
+};
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct PreCommit {
+    /// Linter run before each commit
     pub lint: Option<Lint>,
+    /// Tests run before each commit
     pub test: Option<Test>,
+    /// Built-in checks of the staged files
+    pub hygiene: Option<Hygiene>,
+    /// Built-in secret scanner of the staged changes
+    pub secrets: Option<Secrets>,
+    /// Built-in syntax checks of the staged structured files
+    pub validators: Option<Validators>,
+    /// Commands run before each commit, by name
+    pub tasks: Option<BTreeMap<String, Task>>,
+    /// Checks and tasks run at the same time, the number of CPUs by default
+    pub jobs: Option<usize>,
+    /// Stop at the first failure (default), otherwise run every check and
+    /// summarize the failures
+    #[schemars(default = "default_fail_fast")]
+    pub fail_fast: Option<bool>,
+}
+
+fn default_fail_fast() -> Option<bool> {
+    Some(true)
 }
 
 impl PreCommit {
@@ -17,8 +52,20 @@ impl PreCommit {
         let mut pre_commit = PreCommit {
             lint: None,
             test: None,
+            hygiene: None,
+            secrets: None,
+            validators: None,
+            tasks: None,
+            jobs: None,
+            fail_fast: None,
         };
-        let pre_commit_options = vec!["Linting", "Testing"];
+        let pre_commit_options = vec![
+            "Linting",
+            "Testing",
+            "File hygiene",
+            "Secret scanning",
+            "Structured files validation",
+        ];
         let answers =
             match MultiSelect::new("Select the feature to enable:", pre_commit_options).prompt() {
                 Ok(res) => res,
@@ -35,19 +82,78 @@ impl PreCommit {
                 "Testing" => {
                     pre_commit.test = Some(Test::init());
                 }
+                "File hygiene" => {
+                    pre_commit.hygiene = Some(Hygiene::init());
+                }
+                "Secret scanning" => {
+                    pre_commit.secrets = Some(Secrets::init());
+                }
+                "Structured files validation" => {
+                    pre_commit.validators = Some(Validators::init());
+                }
                 _ => (),
             };
         }
         pre_commit
     }
 
+    /**
+     * Run the checks and the tasks, each after the ones it depends on
+     */
     pub fn run(&self) {
+        let jobs = self.jobs();
+        if let Err(message) = self.check_tasks() {
+            log_error(&message, false);
+            exit(1);
+        }
+        let parallel = self
+            .jobs
+            .unwrap_or_else(|| thread::available_parallelism().map(|n| n.get()).unwrap_or(1));
+        if !tasks::run(&jobs, parallel, self.fail_fast.unwrap_or(true)).is_empty() {
+            exit(1);
+        }
+    }
+
+    /**
+     * The configured checks and tasks, named as in `FISHERMAN_SKIP`
+     */
+    fn jobs(&self) -> Vec<Job<'_>> {
+        let mut jobs: Vec<Job> = Vec::new();
+        if let Some(hygiene) = &self.hygiene {
+            jobs.push(Job::new("hygiene", &hygiene.depends_on, &hygiene.when, || hygiene.execute()));
+        }
+        if let Some(secrets) = &self.secrets {
+            jobs.push(Job::new("secrets", &secrets.depends_on, &secrets.when, || secrets.execute()));
+        }
+        if let Some(validators) = &self.validators {
+            jobs.push(Job::new("validators", &validators.depends_on, &validators.when, || {
+                validators.execute()
+            }));
+        }
         if let Some(test) = &self.test {
-            test.run();
+            jobs.push(Job::new("test", &test.depends_on, &test.when, || test.execute()));
         }
         if let Some(lint) = &self.lint {
-            lint.run();
+            jobs.push(Job::new("lint", &lint.depends_on, &lint.when, || lint.execute()));
+        }
+        for (name, task) in self.tasks.iter().flatten() {
+            jobs.push(Job::new(name, &task.depends_on, &task.when, || tasks::execute(&task.command)));
+        }
+        // Fixing rewrites the staged files, which the other jobs read
+        if self.hygiene.as_ref().is_some_and(|hygiene| hygiene.fix) {
+            tasks::run_alone(&mut jobs, "hygiene");
         }
+        jobs
+    }
+
+    /**
+     * Check the names of the tasks and their dependencies
+     */
+    pub fn check_tasks(&self) -> Result<(), String> {
+        if let Some(name) = self.tasks.iter().flatten().map(|(name, _)| name).find(|name| bypass::CHECKS.contains(&name.as_str())) {
+            return Err(format!("task `{}` has the name of a built-in check", name));
+        }
+        tasks::order(&self.jobs()).map(|_| ())
     }
 }
 
@@ -55,13 +161,59 @@ impl fmt::Display for PreCommit {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut fmt: String = String::from("");
         match &self.lint {
-            Some(lint) => fmt.push_str(&format!(" {}{}", "Lint:\n".bold().green(), lint)),
+            Some(lint) => fmt.push_str(&format!(" {}{}{}{}", "Lint:\n".bold().green(), lint, fmt_depends_on(&lint.depends_on), fmt_when(&lint.when))),
             None => fmt.push_str(" Lint: disabled\n"),
         };
         match &self.test {
-            Some(test) => fmt.push_str(&format!(" {}{}", "Test:\n".bold().green(), test)),
+            Some(test) => fmt.push_str(&format!(" {}{}{}{}", "Test:\n".bold().green(), test, fmt_depends_on(&test.depends_on), fmt_when(&test.when))),
             None => fmt.push_str(" Test: disabled\n"),
         }
+        match &self.hygiene {
+            Some(hygiene) => fmt.push_str(&format!(" {}{}{}{}", "Hygiene:\n".bold().green(), hygiene, fmt_depends_on(&hygiene.depends_on), fmt_when(&hygiene.when))),
+            None => fmt.push_str(" Hygiene: disabled\n"),
+        }
+        match &self.secrets {
+            Some(secrets) => fmt.push_str(&format!(" {}{}{}{}", "Secrets:\n".bold().green(), secrets, fmt_depends_on(&secrets.depends_on), fmt_when(&secrets.when))),
+            None => fmt.push_str(" Secrets: disabled\n"),
+        }
+        match &self.validators {
+            Some(validators) => fmt.push_str(&format!(
+                " {}{}{}{}",
+                "Validators:\n".bold().green(),
+                validators,
+                fmt_depends_on(&validators.depends_on),
+                fmt_when(&validators.when)
+            )),
+            None => fmt.push_str(" Validators: disabled\n"),
+        }
+        for (name, task) in self.tasks.iter().flatten() {
+            let args = match &task.command.args {
+                Some(args) => format!("{}", args),
+                None => String::from("None"),
+            };
+            fmt.push_str(&format!(
+                " {}  - Command: {}\n  - Command Args: {}\n{}{}",
+                format!("Task {}:\n", name).bold().green(),
+                fmt_single_parameter(&task.command.name),
+                args,
+                fmt_depends_on(&task.depends_on),
+                fmt_when(&task.when)
+            ));
+        }
         write!(f, "{}", fmt)
     }
 }
+
+fn fmt_when(when: &Option<When>) -> String {
+    match when {
+        Some(when) => format!("  - When: {}\n", when),
+        None => String::new(),
+    }
+}
+
+fn fmt_depends_on(depends_on: &Option<Vec<String>>) -> String {
+    match depends_on {
+        Some(depends_on) => format!("  - Depends on: {}\n", depends_on.join(", ").purple()),
+        None => String::new(),
+    }
+}
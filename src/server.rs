@@ -0,0 +1,237 @@
+use colored::Colorize;
+use core::fmt;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    env,
+    fs::{self, DirBuilder, File},
+    io::{self, Read},
+    os::unix::fs::DirBuilderExt,
+    path::{Component, Path, PathBuf},
+    process::{exit, Command},
+};
+
+use crate::{commit_message::CommitMessage, git, linter::Lint, logger::log_error, placeholders::Context};
+
+/**
+ * Policies enforced on pushed commits by the `pre-receive` (or `update`) hook
+ * of a shared repository. Commits are read from the object database, no
+ * working tree is needed.
+ */
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Server {
+    /// Validate every pushed commit message with `[commit_message]`
+    pub commit_message: bool,
+    /// Lint the files changed by every pushed commit with `[pre_commit.lint]`
+    pub lint: bool,
+}
+
+/**
+ * A ref update as received by `pre-receive`: `<old> <new> <ref>`, the old
+ * value is not needed to find the pushed commits
+ */
+pub struct RefUpdate {
+    pub new: String,
+    pub name: String,
+}
+
+impl RefUpdate {
+    pub fn parse(line: &str) -> Option<RefUpdate> {
+        let mut fields = line.split_whitespace().skip(1);
+        Some(RefUpdate {
+            new: fields.next()?.to_string(),
+            name: fields.next()?.to_string(),
+        })
+    }
+
+    /**
+     * Commits pushed by this update, oldest first. Commits already reachable
+     * from a ref were checked when they were first pushed.
+     */
+    fn new_commits(&self) -> Vec<String> {
+        if is_null(&self.new) {
+            // The ref is being deleted
+            return Vec::new();
+        }
+        match git::git(&["rev-list", "--reverse", &self.new, "--not", "--all"]) {
+            Some(commits) => commits.lines().map(String::from).collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+impl Server {
+    /**
+     * `pre-receive` reads one ref update per stdin line
+     */
+    pub fn pre_receive(&self, stdin: &[u8], commit_message: Option<&CommitMessage>, lint: Option<&Lint>) {
+        let updates: Vec<RefUpdate> = String::from_utf8_lossy(stdin)
+            .lines()
+            .filter_map(RefUpdate::parse)
+            .collect();
+        self.check(&updates, commit_message, lint);
+    }
+
+    /**
+     * `update` receives a single ref update as `<ref> <old> <new>`
+     */
+    pub fn update(&self, git_args: &[String], commit_message: Option<&CommitMessage>, lint: Option<&Lint>) {
+        if let [name, _old, new] = git_args {
+            let update = RefUpdate {
+                new: new.clone(),
+                name: name.clone(),
+            };
+            self.check(&[update], commit_message, lint);
+        } else {
+            log_error("update requires <ref> <old> <new>", true);
+        }
+    }
+
+    /**
+     * Check every new commit of `updates`, report each rejected commit and
+     * exit with failure if there is any
+     */
+    fn check(&self, updates: &[RefUpdate], commit_message: Option<&CommitMessage>, lint: Option<&Lint>) {
+        let mut rejected = 0;
+        for update in updates {
+            for commit in update.new_commits() {
+                let mut reasons: Vec<String> = Vec::new();
+                if let (true, Some(commit_message)) = (self.commit_message, commit_message) {
+                    let message = git::git(&["log", "-1", "--format=%B", &commit]).unwrap_or_default();
+                    let (success, sout, serr) = validate_message(commit_message, &message, &commit);
+                    if !success {
+                        reasons.push(format!("commit message rejected\n{}{}", sout, serr));
+                    }
+                }
+                if let (true, Some(lint)) = (self.lint, lint) {
+                    let (success, sout, serr) = lint_commit(lint, &commit);
+                    if !success {
+                        reasons.push(format!("lint failed\n{}{}", sout, serr));
+                    }
+                }
+                for reason in &reasons {
+                    eprintln!("{} {} {}: {}", "".red(), &commit[..commit.len().min(10)], update.name, reason.trim_end());
+                }
+                if !reasons.is_empty() {
+                    rejected += 1;
+                }
+            }
+        }
+        if rejected > 0 {
+            log_error(&format!("push rejected, {} commit(s) do not follow the policy", rejected), false);
+            exit(1);
+        }
+    }
+}
+
+/**
+ * Validate the `message` of `commit`, written to a temporary file for the
+ * commands reading `{message_file}`
+ */
+fn validate_message(commit_message: &CommitMessage, message: &str, commit: &str) -> (bool, String, String) {
+    let dir = match private_dir() {
+        Ok(dir) => dir,
+        Err(e) => return (false, String::new(), format!("cannot create a temporary directory: {}", e)),
+    };
+    let path = dir.join("COMMIT_EDITMSG");
+    let result = match fs::write(&path, message) {
+        Ok(_) => commit_message.validate(message, Some(&path.to_string_lossy())),
+        Err(e) => (false, String::new(), format!("cannot write the message of {}: {}", commit, e)),
+    };
+    let _ = fs::remove_dir_all(&dir);
+    result
+}
+
+/**
+ * Lint the files changed by `commit`, checked out from the object database
+ * into a temporary directory
+ */
+fn lint_commit(lint: &Lint, commit: &str) -> (bool, String, String) {
+    let files: Vec<String> = match git::git(&["diff-tree", "--root", "--no-commit-id", "--name-only", "--diff-filter=d", "-r", "-z", commit]) {
+        Some(files) => files.split('\0').filter(|file| !file.is_empty()).map(String::from).collect(),
+        None => return (false, String::new(), format!("cannot list the files of {}", commit)),
+    };
+    // The paths come from the pushed tree, which nothing vouches for
+    if let Some(file) = files.iter().find(|file| !is_relative_path(file)) {
+        return (false, String::new(), format!("invalid path `{}`", file));
+    }
+    let dir = match private_dir() {
+        Ok(dir) => dir,
+        Err(e) => return (false, String::new(), format!("cannot create a temporary directory: {}", e)),
+    };
+    for file in &files {
+        if let Err(e) = extract(commit, file, &dir.join(file)) {
+            let _ = fs::remove_dir_all(&dir);
+            return (false, String::new(), format!("cannot extract {}: {}", file, e));
+        }
+    }
+    let result = lint.lint_files(&files, Some(&dir), Context::default());
+    let _ = fs::remove_dir_all(&dir);
+    result
+}
+
+/**
+ * Whether `path` only descends from the directory it is joined to
+ */
+fn is_relative_path(path: &str) -> bool {
+    let mut components = Path::new(path).components().peekable();
+    components.peek().is_some() && components.all(|component| matches!(component, Component::Normal(_)))
+}
+
+/**
+ * Create a new directory only the current user can access, with a name
+ * other users cannot guess. Creation fails rather than reuse an existing
+ * path, a symlink included.
+ */
+fn private_dir() -> io::Result<PathBuf> {
+    let mut random = [0u8; 16];
+    File::open("/dev/urandom")?.read_exact(&mut random)?;
+    let name: String = random.iter().map(|byte| format!("{:02x}", byte)).collect();
+    let dir = env::temp_dir().join(format!("fisherman-{}", name));
+    DirBuilder::new().mode(0o700).create(&dir)?;
+    Ok(dir)
+}
+
+fn extract(commit: &str, file: &str, destination: &Path) -> Result<(), String> {
+    let output = Command::new("git")
+        .args(["cat-file", "blob", &format!("{}:{}", commit, file)])
+        .output()
+        .map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).to_string());
+    }
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    fs::write(destination, output.stdout).map_err(|e| e.to_string())
+}
+
+fn is_null(hash: &str) -> bool {
+    hash.chars().all(|c| c == '0')
+}
+
+impl fmt::Display for Server {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            " - Validate Commit Messages: {}\n - Lint Pushed Files: {}\n",
+            self.commit_message.to_string().purple(),
+            self.lint.to_string().purple()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_relative_path;
+
+    #[test]
+    fn test_is_relative_path() {
+        assert!(is_relative_path("src/main.rs"));
+        assert!(!is_relative_path("../outside"));
+        assert!(!is_relative_path("src/../../outside"));
+        assert!(!is_relative_path("/etc/passwd"));
+        assert!(!is_relative_path(""));
+    }
+}
@@ -0,0 +1,111 @@
+use colored::Colorize;
+use core::fmt;
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{fmt_single_parameter, Cmd},
+    git,
+    hooks::run_command,
+    logger::log_error,
+};
+
+/// Hash of the empty tree, the "previous HEAD" of a fresh clone
+const EMPTY_TREE: &str = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+
+/**
+ * Something to do after `post-checkout` or `post-merge` when a file matching
+ * `files` changed, e.g. fetching dependencies when `Cargo.lock` changed.
+ */
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SyncAction {
+    /// Regex of the changed files triggering the action
+    pub files: String,
+    /// Command to run
+    pub command: Option<Cmd>,
+    /// Message to print
+    pub message: Option<String>,
+}
+
+/**
+ * Run the `actions` matching the files changed between `old` and `new`
+ */
+pub fn run(hook: &str, actions: &[SyncAction], old: &str, new: &str) {
+    let old = if old.chars().all(|c| c == '0') { EMPTY_TREE } else { old };
+    let changed = match git::git(&["diff", "--name-only", old, new]) {
+        Some(changed) => changed,
+        None => {
+            log_error(&format!("cannot list the files changed by {}", hook), false);
+            return;
+        }
+    };
+    let changed: Vec<&str> = changed.lines().collect();
+    for action in actions {
+        let re = match Regex::new(&action.files) {
+            Ok(re) => re,
+            Err(e) => {
+                log_error(&format!("invalid files regex `{}`: {}", action.files, e), false);
+                continue;
+            }
+        };
+        if !changed.iter().any(|file| re.is_match(file)) {
+            continue;
+        }
+        if let Some(message) = &action.message {
+            eprintln!("{} {}", "Fisherman:".bold(), message);
+        }
+        if let Some(command) = &action.command {
+            if !command.check() {
+                log_error(&format!("Command `{}` not found, make sure it is installed", command.name), false);
+                continue;
+            }
+            // The checkout or merge already happened, a failure is only reported
+            let (success, sout, serr) = run_command(hook, command, &[], &[]);
+            if !success {
+                eprintln!("{}", sout);
+                eprintln!("{}", serr);
+            }
+        }
+    }
+}
+
+/**
+ * `post-checkout` receives the previous and new HEAD, and whether a branch
+ * (rather than files) was checked out
+ */
+pub fn post_checkout(actions: &[SyncAction], git_args: &[String]) {
+    if let [old, new, branch] = git_args {
+        if branch == "1" {
+            run("post-checkout", actions, old, new);
+        }
+    }
+}
+
+/**
+ * `post-merge` compares the HEAD before the merge with the merged one
+ */
+pub fn post_merge(actions: &[SyncAction]) {
+    run("post-merge", actions, "ORIG_HEAD", "HEAD");
+}
+
+impl fmt::Display for SyncAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let command = match &self.command {
+            Some(command) => fmt_single_parameter(&command.describe()),
+            None => String::from("None"),
+        };
+        let message = match &self.message {
+            Some(message) => fmt_single_parameter(message),
+            None => String::from("None"),
+        };
+        write!(
+            f,
+            " - Files: {}\n - Command: {}\n - Message: {}\n",
+            fmt_single_parameter(&self.files),
+            command,
+            message
+        )
+    }
+}
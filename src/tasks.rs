@@ -0,0 +1,379 @@
+use colored::Colorize;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use spinners::{Spinner, Spinners, Stream};
+use std::{
+    any::Any,
+    collections::BTreeSet,
+    panic::{self, AssertUnwindSafe},
+    process::{exit, Output},
+    sync::mpsc,
+    thread,
+};
+
+use crate::{
+    bypass,
+    conditions::{runs, When},
+    config::Cmd,
+    logger::log_error,
+    placeholders::Context,
+};
+
+/**
+ * A command run before each commit, once the tasks it depends on succeeded
+ */
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Task {
+    /// Command of the task
+    pub command: Cmd,
+    /// Tasks that must succeed first
+    pub depends_on: Option<Vec<String>>,
+    /// Conditions the task runs under
+    pub when: Option<When>,
+}
+
+/**
+ * Run `command` with the pre-commit placeholders and its retries, return
+ * whether it succeeded and the output of a failure or the retry it passed on
+ */
+pub fn execute(command: &Cmd) -> (bool, String) {
+    if !command.check() {
+        return (
+            false,
+            format!("Command `{}` not found, make sure it is installed", command.name),
+        );
+    }
+    // Jobs run on their own thread and report a wrong placeholder as a failure
+    let args = match command.expand_args(&Context::hook("pre-commit")) {
+        Ok(args) => args,
+        Err(message) => return (false, message),
+    };
+    match command.retrying(|| command.command(None).args(&args).output()) {
+        Ok((output, 0)) if output.status.success() => (true, String::new()),
+        // A flaky pass is reported apart from a clean one
+        Ok((output, retry)) if output.status.success() => {
+            (true, format!("{} {}", "[Warning]:".yellow(), command.fmt_retry(retry)))
+        }
+        Ok((output, _)) => (false, output_of(&output)),
+        Err(e) => (false, e.to_string()),
+    }
+}
+
+/**
+ * Stdout and stderr of a command, for the report of a failed check
+ */
+fn output_of(output: &Output) -> String {
+    let sout = String::from_utf8_lossy(&output.stdout);
+    let serr = String::from_utf8_lossy(&output.stderr);
+    format!("{}\n{}", sout.trim_end(), serr.trim_end()).trim().to_string()
+}
+
+/**
+ * A node of the pre-commit graph: a built-in check or a task
+ */
+pub struct Job<'a> {
+    pub name: String,
+    pub depends_on: Vec<String>,
+    /// Jobs that must have finished first, whether they succeeded or not
+    pub after: Vec<String>,
+    pub when: Option<&'a When>,
+    pub execute: Box<dyn Fn() -> (bool, String) + Sync + 'a>,
+}
+
+impl<'a> Job<'a> {
+    pub fn new(
+        name: &str,
+        depends_on: &Option<Vec<String>>,
+        when: &'a Option<When>,
+        execute: impl Fn() -> (bool, String) + Sync + 'a,
+    ) -> Job<'a> {
+        Job {
+            name: name.to_string(),
+            depends_on: depends_on.clone().unwrap_or_default(),
+            after: Vec::new(),
+            when: when.as_ref(),
+            execute: Box::new(execute),
+        }
+    }
+}
+
+/**
+ * Make every job `name` does not depend on wait for it, so that a job
+ * modifying the working tree and the index runs alone
+ */
+pub fn run_alone(jobs: &mut [Job], name: &str) {
+    // `name` and its dependencies, transitively
+    let mut before: Vec<String> = vec![name.to_string()];
+    let mut index = 0;
+    while index < before.len() {
+        if let Some(job) = jobs.iter().find(|job| job.name == before[index]) {
+            for dependency in &job.depends_on {
+                if !before.contains(dependency) {
+                    before.push(dependency.clone());
+                }
+            }
+        }
+        index += 1;
+    }
+    for job in jobs.iter_mut().filter(|job| !before.contains(&job.name)) {
+        job.after.push(name.to_string());
+    }
+}
+
+/**
+ * Names of `jobs` ordered so that each follows its dependencies. Fails on
+ * a duplicated name, an unknown dependency or a cycle.
+ */
+pub fn order(jobs: &[Job]) -> Result<Vec<String>, String> {
+    let mut names: BTreeSet<&str> = BTreeSet::new();
+    for job in jobs {
+        if !names.insert(&job.name) {
+            return Err(format!("task `{}` is defined twice", job.name));
+        }
+    }
+    for job in jobs {
+        if let Some(unknown) = job.depends_on.iter().find(|name| !names.contains(name.as_str())) {
+            return Err(format!("task `{}` depends on unknown task `{}`", job.name, unknown));
+        }
+    }
+    let mut ordered: Vec<String> = Vec::new();
+    for job in jobs {
+        visit(jobs, &job.name, &mut Vec::new(), &mut ordered)?;
+    }
+    Ok(ordered)
+}
+
+fn visit(jobs: &[Job], name: &str, path: &mut Vec<String>, ordered: &mut Vec<String>) -> Result<(), String> {
+    if ordered.iter().any(|done| done == name) {
+        return Ok(());
+    }
+    if let Some(start) = path.iter().position(|visiting| visiting == name) {
+        let mut cycle = path[start..].to_vec();
+        cycle.push(name.to_string());
+        return Err(format!("`depends_on` cycle: {}", cycle.join(" -> ")));
+    }
+    path.push(name.to_string());
+    if let Some(job) = jobs.iter().find(|job| job.name == name) {
+        for dependency in &job.depends_on {
+            visit(jobs, dependency, path, ordered)?;
+        }
+    }
+    path.pop();
+    ordered.push(name.to_string());
+    Ok(())
+}
+
+/**
+ * Run `jobs`, up to `parallel` at a time, each once its dependencies
+ * succeeded or were skipped. With `fail_fast` no job is started after a
+ * failure, otherwise every job not depending on a failure runs and the
+ * failures are summarized at the end. Return the failed jobs.
+ */
+pub fn run(jobs: &[Job], parallel: usize, fail_fast: bool) -> Vec<String> {
+    let ordered = match order(jobs) {
+        Ok(ordered) => ordered,
+        Err(message) => {
+            log_error(&message, false);
+            exit(1);
+        }
+    };
+    // Skipped jobs satisfy their dependents
+    let mut done: BTreeSet<String> = BTreeSet::new();
+    let mut pending: Vec<&Job> = Vec::new();
+    for name in &ordered {
+        let job = jobs.iter().find(|job| &job.name == name).unwrap();
+        if bypass::allowed("pre-commit", name) && runs(name, &job.when.cloned()) {
+            pending.push(job);
+        } else {
+            done.insert(name.clone());
+        }
+    }
+
+    // Failed jobs with their report
+    let mut failed: Vec<(String, String)> = Vec::new();
+    // Jobs that cannot run since a dependency failed or is blocked
+    let mut blocked: BTreeSet<String> = BTreeSet::new();
+    thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+        let mut sender = Some(sender);
+        let mut running: Vec<String> = Vec::new();
+        loop {
+            let stopped = fail_fast && !failed.is_empty();
+            while let Some(sender) = sender.as_ref().filter(|_| !stopped && running.len() < parallel.max(1)) {
+                let finished = |name: &String| {
+                    done.contains(name) || blocked.contains(name) || failed.iter().any(|(failed, _)| failed == name)
+                };
+                let Some(index) = pending.iter().position(|job| {
+                    job.depends_on.iter().all(|dependency| done.contains(dependency)) && job.after.iter().all(finished)
+                }) else {
+                    break;
+                };
+                let job = pending.remove(index);
+                running.push(job.name.clone());
+                let sender = sender.clone();
+                scope.spawn(move || {
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| (job.execute)()))
+                        .unwrap_or_else(|payload| (false, panic_message(payload)));
+                    sender.send((job.name.clone(), result))
+                });
+            }
+            // Once nothing can start, a job ending without a result ends the wait
+            if stopped || pending.iter().all(|job| blocked.contains(&job.name)) {
+                sender = None;
+            }
+            if running.is_empty() {
+                break;
+            }
+            let mut spinner = Spinner::with_stream(
+                Spinners::Dots,
+                format!("Fisherman: running {}", running.join(", ")),
+                Stream::Stderr,
+            );
+            let Ok((name, (success, details))) = receiver.recv() else {
+                spinner.stop_and_persist(&format!("{}", "".red()), format!("Fisherman: {} failed", running.join(", ")));
+                failed.extend(running.drain(..).map(|name| (name, String::from("the check ended without a result"))));
+                break;
+            };
+            running.retain(|running| running != &name);
+            if success {
+                spinner.stop_and_persist(&format!("{}", "".green()), format!("Fisherman: {} OK", name));
+                done.insert(name);
+            } else {
+                spinner.stop_and_persist(&format!("{}", "".red()), format!("Fisherman: {} failed", name));
+                failed.push((name, details.clone()));
+                block_dependents(&pending, &failed, &mut blocked);
+            }
+            // Without fail_fast the failures are reported by the summary
+            if !details.is_empty() && (success || fail_fast) {
+                eprintln!("{}", details);
+            }
+        }
+    });
+
+    for job in pending {
+        let reason = match job.depends_on.iter().find(|dependency| !done.contains(*dependency)) {
+            Some(dependency) if failed.iter().any(|(name, _)| name == dependency) => format!("`{}` failed", dependency),
+            Some(dependency) => format!("`{}` did not run", dependency),
+            None => String::from("a previous task failed"),
+        };
+        eprintln!("{} Fisherman: {} skipped, {}", "".yellow(), job.name, reason);
+    }
+    if !fail_fast && !failed.is_empty() {
+        summarize(&failed);
+    }
+    failed.into_iter().map(|(name, _)| name).collect()
+}
+
+/**
+ * Add to `blocked` the pending jobs depending on a failed or blocked job
+ */
+fn block_dependents(pending: &[&Job], failed: &[(String, String)], blocked: &mut BTreeSet<String>) {
+    loop {
+        let newly: Vec<String> = pending
+            .iter()
+            .filter(|job| !blocked.contains(&job.name))
+            .filter(|job| {
+                job.depends_on
+                    .iter()
+                    .any(|dependency| blocked.contains(dependency) || failed.iter().any(|(name, _)| name == dependency))
+            })
+            .map(|job| job.name.clone())
+            .collect();
+        if newly.is_empty() {
+            return;
+        }
+        blocked.extend(newly);
+    }
+}
+
+/**
+ * Report of a job that panicked
+ */
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    let message = payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_default();
+    format!("the check panicked: {}", message)
+}
+
+/**
+ * Report every failure at once, after all the checks ran
+ */
+fn summarize(failed: &[(String, String)]) {
+    let names: Vec<&str> = failed.iter().map(|(name, _)| name.as_str()).collect();
+    eprintln!(
+        "\n{} Fisherman: {} failed: {}",
+        "".red(),
+        if failed.len() == 1 { String::from("1 check") } else { format!("{} checks", failed.len()) },
+        names.join(", ")
+    );
+    for (name, details) in failed {
+        eprintln!("\n{}", name.bold());
+        if !details.is_empty() {
+            eprintln!("{}", details);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{order, run, Job};
+    use std::sync::Mutex;
+
+    fn job(name: &str, depends_on: &[&str]) -> Job<'static> {
+        Job {
+            name: name.to_string(),
+            depends_on: depends_on.iter().map(|name| name.to_string()).collect(),
+            after: Vec::new(),
+            when: None,
+            execute: Box::new(|| (true, String::new())),
+        }
+    }
+
+    /**
+     * A job adding its name to `ran` and returning `success`
+     */
+    fn recorded<'a>(name: &str, depends_on: &[&str], success: bool, ran: &'a Mutex<Vec<String>>) -> Job<'a> {
+        let recorded = name.to_string();
+        Job {
+            execute: Box::new(move || {
+                ran.lock().unwrap().push(recorded.clone());
+                (success, String::new())
+            }),
+            ..job(name, depends_on)
+        }
+    }
+
+    #[test]
+    fn test_order() {
+        let jobs = [job("lint", &["codegen"]), job("test", &["build"]), job("codegen", &[]), job("build", &["codegen"])];
+        assert_eq!(vec!["codegen", "lint", "build", "test"], order(&jobs).unwrap());
+        let jobs = [job("a", &["b"]), job("b", &["c"]), job("c", &["a"])];
+        assert_eq!(Err(String::from("`depends_on` cycle: a -> b -> c -> a")), order(&jobs));
+        assert!(order(&[job("lint", &["missing"])]).is_err());
+        assert!(order(&[job("lint", &[]), job("lint", &[])]).is_err());
+    }
+
+    #[test]
+    fn test_run() {
+        let ran = Mutex::new(Vec::new());
+        let jobs = [
+            recorded("codegen", &[], false, &ran),
+            recorded("lint", &["codegen"], true, &ran),
+            recorded("test", &["lint"], true, &ran),
+            recorded("hygiene", &[], true, &ran),
+            recorded("secrets", &[], false, &ran),
+        ];
+        // Every failure is collected, the dependents of one are skipped
+        assert_eq!(vec!["codegen", "secrets"], run(&jobs, 1, false));
+        assert_eq!(vec!["codegen", "hygiene", "secrets"], *ran.lock().unwrap());
+
+        // No job starts after a failure
+        ran.lock().unwrap().clear();
+        assert_eq!(vec!["codegen"], run(&jobs, 1, true));
+        assert_eq!(vec!["codegen"], *ran.lock().unwrap());
+    }
+}
@@ -1,61 +1,61 @@
 use colored::Colorize;
 use core::fmt;
 use spinners::{Spinner, Spinners};
-use std::process::{exit, Command};
+use std::process::exit;
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    conditions::When,
     config::{fmt_single_parameter, Cmd},
-    logger::log_error,
+    tasks,
 };
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Test {
+    /// Test command
     pub command: Cmd,
+    /// Conditions the check runs under
+    pub when: Option<When>,
+    /// Checks and tasks that must succeed first
+    pub depends_on: Option<Vec<String>>,
 }
 
 impl Test {
     pub fn init() -> Test {
         let command = Cmd::init("Tester program:");
-        Test { command }
+        Test {
+            command,
+            when: None,
+            depends_on: None,
+        }
     }
 
     pub fn run(&self) {
-        if !self.command.check() {
-            log_error(
-                &format!(
-                    "Command `{}` not found, make sure it is installed",
-                    &self.command.name
-                ),
-                true,
-            );
-        }
-
-        let binding = Vec::new();
-        let args: &Vec<String> = match &self.command.args {
-            Some(args) => &args.0,
-            None => &binding,
-        };
-
         let mut spinner = Spinner::with_stream(Spinners::Dots, "Fisherman: Testing in progress".into(), spinners::Stream::Stderr);
-        let output = Command::new(&self.command.name)
-            .args(args)
-            .output()
-            .expect("");
-        let success = output.status.success();
+        let (success, report) = self.execute();
 
         if success {
             spinner.stop_and_persist(&format!("{}", "".green()), "Fisherman: Testing OK".into());
         } else {
             spinner.stop_and_persist(&format!("{}", "".red()), "Fisherman: Testing Failed".into());
-            let sout = String::from_utf8(output.stdout).expect("Not UTF-8");
-            let serr = String::from_utf8(output.stderr).expect("Not UTF-8");
-            eprintln!("{}", sout);
-            eprintln!("{}", serr);
+        }
+        if !report.is_empty() {
+            eprintln!("{}", report);
+        }
+        if !success {
             exit(1);
         }
     }
+
+    /**
+     * Run the tests, return whether they passed and their output
+     */
+    pub fn execute(&self) -> (bool, String) {
+        tasks::execute(&self.command)
+    }
 }
 
 impl fmt::Display for Test {
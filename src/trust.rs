@@ -0,0 +1,208 @@
+use colored::Colorize;
+use inquire::Confirm;
+use sha2::{Digest, Sha256};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{config::Config, logger::log_error};
+
+/**
+ * Commands approved for the configuration at `config_path`, kept in the user
+ * data directory so a cloned repository cannot approve its own commands.
+ */
+pub struct TrustStore {
+    path: PathBuf,
+}
+
+impl TrustStore {
+    pub fn open(config_path: &Path) -> Option<TrustStore> {
+        let config_path = config_path.canonicalize().ok()?;
+        let key = hash(&[config_path.to_string_lossy().to_string()]);
+        Some(TrustStore {
+            path: data_home()?.join("fisherman").join("trusted").join(key),
+        })
+    }
+
+    /**
+     * Commands approved last time, the first line of the store is their hash
+     */
+    fn approved(&self) -> Option<(String, Vec<String>)> {
+        let content = fs::read_to_string(&self.path).ok()?;
+        let mut lines = content.lines();
+        let digest = lines.next()?.to_string();
+        Some((digest, lines.map(String::from).collect()))
+    }
+
+    fn approve(&self, commands: &[String]) -> std::io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut content = hash(commands);
+        for command in commands {
+            content.push('\n');
+            content.push_str(command);
+        }
+        fs::write(&self.path, content)
+    }
+}
+
+/**
+ * Whether the commands of `config` were approved with `fisherman trust`
+ */
+pub fn is_trusted(config: &Config, config_path: &Path) -> bool {
+    let commands = config.commands();
+    commands.is_empty()
+        || TrustStore::open(config_path)
+            .and_then(|store| store.approved())
+            .is_some_and(|(digest, _)| digest == hash(&commands))
+}
+
+/**
+ * Exit unless the commands of `config` were approved with `fisherman trust`
+ */
+pub fn check(config: &Config, config_path: &Path) {
+    let commands = config.commands();
+    if commands.is_empty() {
+        return;
+    }
+    let store = match TrustStore::open(config_path) {
+        Some(store) => store,
+        None => {
+            log_error("cannot locate the trust store, is HOME set?", true);
+            unreachable!();
+        }
+    };
+    match store.approved() {
+        Some((digest, _)) if digest == hash(&commands) => (),
+        Some((_, approved)) => {
+            log_error("the commands in the configuration changed since they were trusted:", false);
+            print_diff(&approved, &commands);
+            log_error("review them and run `fisherman trust` to allow them", true);
+        }
+        None => {
+            log_error("this configuration is not trusted yet, it would run:", false);
+            print_diff(&[], &commands);
+            log_error("review them and run `fisherman trust` to allow them", true);
+        }
+    }
+}
+
+/**
+ * Show what changed since the last approval and ask to trust the commands
+ */
+pub fn trust(config: &Config, config_path: &Path, ask: bool) {
+    let commands = config.commands();
+    let store = match TrustStore::open(config_path) {
+        Some(store) => store,
+        None => {
+            log_error("cannot locate the trust store, is HOME set?", true);
+            unreachable!();
+        }
+    };
+    let approved = match store.approved() {
+        Some((digest, _)) if digest == hash(&commands) => {
+            eprintln!("Configuration already trusted");
+            return;
+        }
+        Some((_, approved)) => approved,
+        None => Vec::new(),
+    };
+    if ask {
+        print_diff(&approved, &commands);
+        let confirmed = Confirm::new("Trust these commands?")
+            .with_help_message("y/n")
+            .prompt()
+            .unwrap_or(false);
+        if !confirmed {
+            return;
+        }
+    }
+    match store.approve(&commands) {
+        Ok(_) => eprintln!("Configuration trusted"),
+        Err(e) => log_error(&format!("Could not update the trust store: {}", e), true),
+    }
+}
+
+fn print_diff(approved: &[String], commands: &[String]) {
+    for line in diff(approved, commands) {
+        eprintln!("{}", line);
+    }
+}
+
+/**
+ * Lines of the removed, kept and added commands
+ */
+fn diff(approved: &[String], commands: &[String]) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for command in approved.iter().filter(|c| !commands.contains(c)) {
+        lines.push(format!("- {}", command).red().to_string());
+    }
+    for command in commands {
+        if approved.contains(command) {
+            lines.push(format!("  {}", command));
+        } else {
+            lines.push(format!("+ {}", command).green().to_string());
+        }
+    }
+    lines
+}
+
+fn hash(lines: &[String]) -> String {
+    let mut hasher = Sha256::new();
+    for line in lines {
+        hasher.update(line);
+        hasher.update("\n");
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/**
+ * `$XDG_DATA_HOME`, defaulting to `~/.local/share`
+ */
+fn data_home() -> Option<PathBuf> {
+    match env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff, is_trusted, trust};
+    use crate::diagnostics;
+    use colored::Colorize;
+    use std::{env, fs};
+
+    #[test]
+    fn test_trust_store() {
+        let dir = env::temp_dir().join(format!("fisherman-test-trust-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        env::set_var("XDG_DATA_HOME", dir.join("data"));
+        let path = dir.join(".fisherman.toml");
+        fs::write(&path, "").unwrap();
+        let config = diagnostics::parse("version = 3\n[pre_commit.test.command]\nname = \"cargo\"\nargs = [\"test\"]\n").unwrap();
+        let edited = diagnostics::parse("version = 3\n[pre_commit.test.command]\nname = \"curl\"\n").unwrap();
+
+        assert!(!is_trusted(&config, &path));
+        trust(&config, &path, false);
+        let trusted = (is_trusted(&config, &path), is_trusted(&edited, &path));
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!((true, false), trusted);
+    }
+
+    #[test]
+    fn test_diff() {
+        let approved = [String::from("pre-commit test: cargo test"), String::from("commit-msg: true")];
+        let commands = [String::from("commit-msg: true"), String::from("pre-commit test: curl")];
+        assert_eq!(
+            vec![
+                "- pre-commit test: cargo test".red().to_string(),
+                String::from("  commit-msg: true"),
+                "+ pre-commit test: curl".green().to_string(),
+            ],
+            diff(&approved, &commands)
+        );
+    }
+}
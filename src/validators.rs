@@ -0,0 +1,286 @@
+use colored::Colorize;
+use core::fmt;
+use inquire::{Confirm, MultiSelect};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path};
+use toml_edit::{ImDocument, Item};
+
+use crate::{conditions::When, diagnostics, git, logger::log_error};
+
+const FORMATS: [&str; 3] = ["TOML", "JSON", "YAML"];
+const EDITIONS: [&str; 4] = ["2015", "2018", "2021", "2024"];
+
+/**
+ * Syntax checks for the staged structured files. With `schemas` the
+ * fisherman configuration and the Cargo manifests are checked against
+ * their expected layout too.
+ */
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Validators {
+    /// Check TOML files
+    pub toml: bool,
+    /// Check JSON files
+    pub json: bool,
+    /// Check YAML files
+    pub yaml: bool,
+    /// Check the layout of `.fisherman.toml` and `Cargo.toml`
+    pub schemas: bool,
+    /// Conditions the check runs under
+    pub when: Option<When>,
+    /// Checks and tasks that must succeed first
+    pub depends_on: Option<Vec<String>>,
+}
+
+/**
+ * A parse error at a 1-based line and column
+ */
+#[derive(Debug)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+/**
+ * Just enough of a Cargo manifest to report mistakes in its main keys
+ */
+#[derive(Deserialize)]
+struct Manifest {
+    package: Option<Package>,
+    dependencies: Option<BTreeMap<String, Dependency>>,
+    #[serde(rename = "dev-dependencies")]
+    dev_dependencies: Option<BTreeMap<String, Dependency>>,
+    #[serde(rename = "build-dependencies")]
+    build_dependencies: Option<BTreeMap<String, Dependency>>,
+}
+
+#[derive(Deserialize)]
+struct Package {
+    name: toml::Value,
+    edition: Option<toml::Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Dependency {
+    Version(String),
+    Detailed(toml::Table),
+}
+
+impl Validators {
+    pub fn init() -> Validators {
+        let mut validators = Validators::default();
+        let answers = match MultiSelect::new("Select the formats to validate:", FORMATS.to_vec()).prompt() {
+            Ok(res) => res,
+            Err(e) => {
+                log_error(&e.to_string(), true);
+                unreachable!();
+            }
+        };
+        for format in answers {
+            match format {
+                "TOML" => validators.toml = true,
+                "JSON" => validators.json = true,
+                "YAML" => validators.yaml = true,
+                _ => (),
+            }
+        }
+        validators.schemas = Confirm::new("Check .fisherman.toml and Cargo.toml layout?")
+            .with_help_message("y/n")
+            .prompt()
+            .unwrap();
+        validators
+    }
+
+    /**
+     * Check the staged structured files, return whether they are valid and
+     * the errors found
+     */
+    pub fn execute(&self) -> (bool, String) {
+        let mut errors: Vec<String> = Vec::new();
+        for path in git::staged_files() {
+            let content = match git::staged_content(&path) {
+                Some(content) => String::from_utf8_lossy(&content).to_string(),
+                None => continue,
+            };
+            if let Some(error) = self.validate(&path, &content) {
+                errors.push(format!("{}:{}:{}: {}", path, error.line, error.column, error.message));
+            }
+        }
+        (errors.is_empty(), errors.join("\n"))
+    }
+
+    /**
+     * First error found in `content`, according to the extension of `path`
+     */
+    pub fn validate(&self, path: &str, content: &str) -> Option<Location> {
+        let file_name = Path::new(path).file_name()?.to_string_lossy().to_string();
+        let extension = Path::new(path).extension().map(|ext| ext.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("toml") if self.schemas && file_name == ".fisherman.toml" => {
+                diagnostics::parse(content).err()
+            }
+            Some("toml") if self.schemas && file_name == "Cargo.toml" => validate_manifest(content),
+            Some("toml") if self.toml => toml_error(content, toml::from_str::<toml::Table>(content).err()),
+            Some("json") if self.json => serde_json::from_str::<serde_json::Value>(content)
+                .err()
+                .map(|e| Location {
+                    line: e.line(),
+                    column: e.column(),
+                    message: e.to_string(),
+                }),
+            Some("yaml" | "yml") if self.yaml => validate_yaml(content),
+            _ => None,
+        }
+    }
+}
+
+pub fn toml_error(content: &str, error: Option<toml::de::Error>) -> Option<Location> {
+    let error = error?;
+    let (line, column) = match error.span() {
+        Some(span) => position(content, span.start),
+        None => (1, 1),
+    };
+    Some(Location {
+        line,
+        column,
+        message: error.message().to_string(),
+    })
+}
+
+fn validate_manifest(content: &str) -> Option<Location> {
+    let manifest: Manifest = match toml::from_str(content) {
+        Ok(manifest) => manifest,
+        Err(e) => return toml_error(content, Some(e)),
+    };
+    // Only the immutable document keeps the spans of the keys
+    let document = ImDocument::parse(content).ok();
+    let at = |path: &[&str], message: String| {
+        let (line, column) = document
+            .as_ref()
+            .and_then(|document| key_span(document.as_item(), path))
+            .map(|offset| position(content, offset))
+            .unwrap_or((1, 1));
+        Some(Location { line, column, message })
+    };
+    if let Some(package) = &manifest.package {
+        if !package.name.is_str() {
+            return at(&["package", "name"], String::from("`package.name` must be a string"));
+        }
+        if let Some(edition) = &package.edition {
+            if !edition.as_str().is_some_and(|edition| EDITIONS.contains(&edition)) {
+                return at(&["package", "edition"], format!("`package.edition` must be one of {}", EDITIONS.join(", ")));
+            }
+        }
+    }
+    let sections = [
+        ("dependencies", &manifest.dependencies),
+        ("dev-dependencies", &manifest.dev_dependencies),
+        ("build-dependencies", &manifest.build_dependencies),
+    ];
+    for (section, dependencies) in sections {
+        for (name, dependency) in dependencies.iter().flatten() {
+            match dependency {
+                Dependency::Version(version) if version.trim().is_empty() => {
+                    return at(&[section, name], format!("`{}.{}` has an empty version", section, name));
+                }
+                Dependency::Detailed(table)
+                    if !["version", "path", "git", "workspace"].iter().any(|key| table.contains_key(*key)) =>
+                {
+                    return at(
+                        &[section, name],
+                        format!("`{}.{}` needs a `version`, `path`, `git` or `workspace` key", section, name),
+                    );
+                }
+                _ => (),
+            }
+        }
+    }
+    None
+}
+
+/**
+ * Offset of the last key of `path` under `item`
+ */
+fn key_span(item: &Item, path: &[&str]) -> Option<usize> {
+    let (first, rest) = path.split_first()?;
+    let (key, value) = item.as_table_like()?.get_key_value(first)?;
+    if rest.is_empty() {
+        return key.span().map(|span| span.start);
+    }
+    key_span(value, rest)
+}
+
+fn validate_yaml(content: &str) -> Option<Location> {
+    // A YAML file may hold several documents
+    for document in serde_yaml::Deserializer::from_str(content) {
+        if let Err(e) = serde_yaml::Value::deserialize(document) {
+            let (line, column) = match e.location() {
+                Some(location) => (location.line(), location.column()),
+                None => (1, 1),
+            };
+            return Some(Location {
+                line,
+                column,
+                message: e.to_string(),
+            });
+        }
+    }
+    None
+}
+
+/**
+ * 1-based line and column of the byte `offset` in `content`
+ */
+pub fn position(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map(|i| i + 1).unwrap_or(0) + 1;
+    (line, column)
+}
+
+impl fmt::Display for Validators {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let enabled = [(self.toml, FORMATS[0]), (self.json, FORMATS[1]), (self.yaml, FORMATS[2])];
+        let formats: Vec<&str> = enabled.iter().filter(|(on, _)| *on).map(|(_, name)| *name).collect();
+        write!(
+            f,
+            "  - Formats: {}\n  - Schemas: {}\n",
+            formats.join(", ").yellow(),
+            self.schemas.to_string().purple()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Validators;
+
+    #[test]
+    fn test_validate_locations() {
+        let validators = Validators {
+            toml: true,
+            json: true,
+            yaml: true,
+            schemas: true,
+            when: None,
+            depends_on: None,
+        };
+        let error = validators.validate("a.json", "{\n  \"a\": 1,\n}").unwrap();
+        assert_eq!((3, 1), (error.line, error.column));
+        let error = validators.validate("a.toml", "a = 1\nb = \n").unwrap();
+        assert_eq!(2, error.line);
+        let error = validators.validate("Cargo.toml", "[package]\nname = \"x\"\nedition = \"2020\"\n").unwrap();
+        assert_eq!((3, 1), (error.line, error.column));
+        // The key is located, not its first occurrence in the file
+        let manifest = "# edition\n[package]\nname = \"x\"\nedition = \"2020\"\n[dependencies]\nserde_json = \"1\"\nserde = \" \"\n";
+        let error = validators.validate("Cargo.toml", manifest).unwrap();
+        assert_eq!((4, 1), (error.line, error.column));
+        let error = validators.validate("Cargo.toml", &manifest.replace("2020", "2021")).unwrap();
+        assert_eq!((7, 1), (error.line, error.column));
+        assert!(validators.validate("a.yaml", "a: 1\nb: [\n").is_some());
+        assert!(validators.validate("a.yml", "a: 1\n---\nb: 2\n").is_none());
+    }
+}